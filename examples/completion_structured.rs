@@ -0,0 +1,38 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::env;
+
+use agentic_core::{agent::service::AgentService, capabilities::completion::message::Message};
+
+/// A summary of a company
+#[derive(Deserialize, JsonSchema)]
+struct Company {
+    name: String,
+    /// Stock ticker symbol
+    ticker: String,
+    founded: Option<u32>,
+    products: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY environment variable not set");
+
+    let agent_service = AgentService::new();
+    let agent = agent_service.builder().with_openai(&api_key)?.build()?;
+
+    let message = Message::User {
+        content: "Tell me about Apple".to_string(),
+        response_id: None,
+    };
+
+    let company: Company = agent.complete_structured(&None, &vec![message]).await?;
+    println!("{} ({})", company.name, company.ticker);
+    if let Some(founded) = company.founded {
+        println!("Founded: {}", founded);
+    }
+    println!("Products: {}", company.products.join(", "));
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::{env, io::Write};
+
+use agentic_core::{
+    agent::service::AgentService,
+    capabilities::{
+        client::tool::Tool,
+        completion::{message::Message, stream::CompletionStreamEvent},
+    },
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let api_key =
+        env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY environment variable not set");
+
+    let agent_service = AgentService::new();
+    let agent = agent_service
+        .builder()
+        .with_tool(WeatherTool {})
+        .with_anthropic(&api_key)?
+        .build()?;
+
+    let message = Message::User {
+        content: "what is the weather in paris and San Fransicso".to_string(),
+        response_id: None,
+    };
+
+    // text of every turn is printed as it streams, tools run between the turns
+    let mut stream = agent.complete_with_tools_stream(&None, &vec![message]).await?;
+    while let Some(event) = stream.next().await {
+        match event? {
+            CompletionStreamEvent::TextDelta { text } => {
+                print!("{}", text);
+                std::io::stdout().flush()?;
+            }
+            CompletionStreamEvent::ToolCallStart { name, .. } => println!("\n[calling {}]", name),
+            CompletionStreamEvent::Usage(usage) => {
+                println!("\n[usage in: {} out: {}]", usage.input_tokens, usage.output_tokens)
+            }
+            CompletionStreamEvent::Stop { reason, .. } => println!("\n[stop: {:?}]", reason),
+            CompletionStreamEvent::Error { message } => println!("\n[error: {}]", message),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct WeatherTool {}
+
+#[derive(Deserialize)]
+struct Weather {
+    location: String,
+}
+
+#[async_trait]
+impl Tool for WeatherTool {
+    fn name(&self) -> String {
+        "get_weather".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Get current temperature for a given location".to_string()
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "location": {
+                    "type": "string",
+                    "description": "City and country e.g. Bogotá, Colombia"
+                }
+            },
+            "required": ["location"],
+            "additionalProperties": false
+        })
+    }
+
+    async fn execute(&self, value: serde_json::Value) -> Result<Value> {
+        let weather: Weather = serde_json::from_value(value)?;
+        Ok(json!({ "location": weather.location, "temperature": 20 }))
+    }
+}
@@ -55,15 +55,8 @@ impl Tool for TickerAnalyseTool {
     }
 
     async fn execute(&self, value: serde_json::Value) -> Result<Value> {
-        let ticker: Ticker = match serde_json::from_value(value.clone()) {
-            Ok(c) => c,
-            Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Error dezerializing arguments: {:#?}",
-                    value
-                ));
-            }
-        };
+        // the agent validated the arguments against the schema of parameters
+        let ticker: Ticker = serde_json::from_value(value)?;
         let Some(symbol) = ticker.symbols.iter().find(|&e| e == "AAPL") else {
             return Err(anyhow::anyhow!("Error find Apple stock ticker"));
         };
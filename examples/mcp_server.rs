@@ -0,0 +1,73 @@
+use std::{env, net::SocketAddr};
+
+use agentic_core::capabilities::{
+    client::tool::Tool,
+    tools::{mcp_server::MCPServer, tool::ToolRegistry},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/*
+    Serves native tools to other MCP hosts.
+    cargo run --example mcp_server -- stdio
+    cargo run --example mcp_server -- http 127.0.0.1:8080   (endpoint http://127.0.0.1:8080/mcp)
+*/
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut registry = ToolRegistry::new();
+    registry.register_tool(WeatherTool {});
+    let server = MCPServer::new("weather", registry);
+
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|a| a.as_str()) {
+        Some("http") => {
+            let addr: SocketAddr = args
+                .get(2)
+                .map(|a| a.as_str())
+                .unwrap_or("127.0.0.1:8080")
+                .parse()?;
+            server.serve_http(addr).await
+        }
+        _ => server.serve_stdio().await,
+    }
+}
+
+#[derive(Debug)]
+struct WeatherTool {}
+
+#[derive(Deserialize)]
+struct Weather {
+    location: String,
+}
+
+#[async_trait]
+impl Tool for WeatherTool {
+    fn name(&self) -> String {
+        "get_weather".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Get current temperature for a given location".to_string()
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "location": {
+                    "type": "string",
+                    "description": "City and country e.g. Bogotá, Colombia"
+                }
+            },
+            "required": ["location"],
+            "additionalProperties": false
+        })
+    }
+
+    async fn execute(&self, value: serde_json::Value) -> Result<Value> {
+        let weather: Weather = serde_json::from_value(value)?;
+        Ok(json!({ "location": weather.location, "temperature": 20 }))
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use agentic_core::capabilities::tools::mcp::{MCPRegistry, MCPServerConfig};
+use anyhow::Result;
+use serde_json::json;
+use tracing::Level;
+use tracing_subscriber::{filter, layer::SubscriberExt, util::SubscriberInitExt};
+
+/*
+    Launches a local MCP server as a subprocess and talks to it over stdin/stdout.
+    The server is a small python script, examples/scripts/fake_mcp_server.py.
+*/
+#[tokio::main]
+async fn main() -> Result<()> {
+    let filter = filter::Targets::new().with_target("agentic_core::capabilities", Level::DEBUG);
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().compact().pretty()) // Compact format
+        .with(filter)
+        .init();
+
+    let config = MCPServerConfig::stdio(
+        "Quotes",
+        "python3",
+        &["examples/scripts/fake_mcp_server.py"],
+    );
+
+    let mut registry = MCPRegistry::new();
+    let definitions = registry.register_server(config).await?;
+    println!("Definitions: {:#?}", definitions);
+
+    let definition = registry.register_tool("Quotes", "get_quote").await?;
+    println!("Registered: {:#?}", definition);
+
+    let response = registry
+        .call_tool("Quotes___get_quote", json!({"symbol": "AAPL"}))
+        .await?;
+    println!("Response: {:#?}", response.into_value());
+
+    // resources and prompts can be pulled into the conversation as messages
+    let resources = registry.list_resources("Quotes").await?;
+    println!("Resources: {:#?}", resources);
+    let mut messages = registry
+        .resource_messages("Quotes", &resources[0].uri)
+        .await?;
+
+    let prompts = registry.list_prompts("Quotes").await?;
+    println!("Prompts: {:#?}", prompts);
+    let arguments = HashMap::from([("symbol".to_string(), "AAPL".to_string())]);
+    messages.extend(
+        registry
+            .prompt_messages("Quotes", &prompts[0].name, arguments)
+            .await?,
+    );
+    println!("Messages: {:#?}", messages);
+
+    registry.close().await?;
+    Ok(())
+}
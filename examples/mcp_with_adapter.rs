@@ -4,14 +4,14 @@ use agentic_core::{
         client::mcp::MCPServerAdapter,
         completion::message::Message,
         rcp::JsonRpcRequest,
-        tools::mcp::{MCPRegistry, MCPServerConfig},
+        tools::mcp::MCPServerConfig,
     },
 };
 use anyhow::Result;
 use py_literal::Value as PyValue;
 use serde_json::{json, Map, Value as JsonValue};
 use std::env;
-use tracing::{debug, Level};
+use tracing::Level;
 use tracing_subscriber::{filter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -28,11 +28,7 @@ async fn main() -> Result<()> {
         .init();
 
     let alpha_api_key = env::var("ALPHA_API_KEY").expect("Alpha Vantage API Key not found");
-    let config = MCPServerConfig {
-        name: "Alpha".to_string(),
-        url: "https://mcp.alphavantage.co/mcp".to_string(),
-        api_key: alpha_api_key,
-    };
+    let config = MCPServerConfig::http("Alpha", "https://mcp.alphavantage.co/mcp", &alpha_api_key);
 
     // let api_key =
     //     env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
@@ -0,0 +1,132 @@
+#!/usr/bin/env python3
+# Minimal MCP server over stdio used by the mcp_stdio example.
+# Reads newline delimited JSON-RPC from stdin and writes responses to stdout.
+# With --test it adds the tools used by tests/mcp_stdio.rs.
+import json
+import os
+import sys
+
+# tools/list returns one tool per page to exercise cursor pagination
+PAGE_SIZE = 1
+
+TOOLS = [
+    {
+        "name": "get_quote",
+        "description": "Get the last price for a stock ticker",
+        "inputSchema": {
+            "type": "object",
+            "properties": {"symbol": {"type": "string", "description": "Ticker symbol e.g. AAPL"}},
+            "required": ["symbol"],
+        },
+    }
+]
+
+TEST_TOOLS = [
+    {
+        "name": "slow_echo",
+        "description": "Echo the text, the reply is held until the next request is answered",
+        "inputSchema": {"type": "object", "properties": {"text": {"type": "string"}}},
+    },
+    {
+        "name": "server_pid",
+        "description": "Return the process id of the server",
+        "inputSchema": {"type": "object", "properties": {}},
+    },
+]
+
+if "--test" in sys.argv:
+    TOOLS = TOOLS + TEST_TOOLS
+
+RESOURCES = [
+    {
+        "uri": "file:///notes/market.md",
+        "name": "market.md",
+        "description": "Daily market notes",
+        "mimeType": "text/markdown",
+    }
+]
+
+PROMPTS = [
+    {
+        "name": "analyse_ticker",
+        "description": "Ask for an analysis of a stock ticker",
+        "arguments": [{"name": "symbol", "description": "Ticker symbol", "required": True}],
+    }
+]
+
+
+def reply(id, result=None, error=None):
+    message = {"jsonrpc": "2.0", "id": id}
+    if error is not None:
+        message["error"] = error
+    else:
+        message["result"] = result
+    sys.stdout.write(json.dumps(message) + "\n")
+    sys.stdout.flush()
+
+
+def text_result(text):
+    return {"content": [{"type": "text", "text": text}]}
+
+
+def tools_page(cursor):
+    start = int(cursor) if cursor else 0
+    result = {"tools": TOOLS[start:start + PAGE_SIZE]}
+    if start + PAGE_SIZE < len(TOOLS):
+        result["nextCursor"] = str(start + PAGE_SIZE)
+    return result
+
+
+# replies of slow_echo, sent after the next request so they arrive out of order
+held = []
+
+for line in sys.stdin:
+    if not line.strip():
+        continue
+    request = json.loads(line)
+    method = request.get("method")
+    id = request.get("id")
+    if id is None:
+        # notification
+        continue
+    if method == "initialize":
+        reply(id, {
+            "protocolVersion": request["params"]["protocolVersion"],
+            "capabilities": {"tools": {}, "resources": {}, "prompts": {}},
+            "serverInfo": {"name": "fake-mcp-server", "version": "0.1.0"},
+        })
+    elif method == "tools/list":
+        reply(id, tools_page((request.get("params") or {}).get("cursor")))
+    elif method == "tools/call":
+        name = request["params"]["name"]
+        arguments = request["params"].get("arguments") or {}
+        if name == "slow_echo":
+            held.append((id, arguments.get("text", "")))
+            continue
+        elif name == "server_pid":
+            reply(id, text_result(str(os.getpid())))
+        else:
+            symbol = arguments.get("symbol", "")
+            reply(id, text_result(json.dumps({"symbol": symbol, "price": 187.5})))
+    elif method == "resources/list":
+        reply(id, {"resources": RESOURCES})
+    elif method == "resources/templates/list":
+        reply(id, {"resourceTemplates": [{"uriTemplate": "file:///notes/{day}.md", "name": "notes"}]})
+    elif method == "resources/read":
+        uri = request["params"]["uri"]
+        reply(id, {"contents": [{"uri": uri, "mimeType": "text/markdown", "text": "Tech stocks rallied."}]})
+    elif method == "prompts/list":
+        reply(id, {"prompts": PROMPTS})
+    elif method == "prompts/get":
+        symbol = request["params"]["arguments"].get("symbol", "")
+        reply(id, {
+            "description": "Ticker analysis",
+            "messages": [{"role": "user", "content": {"type": "text", "text": "Analyse the stock %s" % symbol}}],
+        })
+    elif method == "ping":
+        reply(id, {})
+    else:
+        reply(id, error={"code": -32601, "message": "Method not found: %s" % method})
+    while held:
+        held_id, text = held.pop()
+        reply(held_id, text_result(text))
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use agentic_core::{
+    agent::service::AgentService,
+    capabilities::{client::tool::TypedTool, completion::message::Message},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY environment variable not set");
+
+    let agent_service = AgentService::new();
+    let agent = agent_service
+        .builder()
+        .with_tool(WeatherTool {})
+        .with_openai(&api_key)?
+        .build()?;
+
+    let message = Message::User {
+        content: "what is the weather in paris and San Fransicso".to_string(),
+        response_id: None,
+    };
+
+    let response = agent.complete_with_tools(&None, &vec![message]).await?;
+    println!("Response: {}", response.text());
+
+    Ok(())
+}
+
+/// Get the current weather for a given location
+#[derive(Deserialize, JsonSchema)]
+struct WeatherArgs {
+    /// City and country e.g. Bogotá, Colombia
+    location: String,
+    unit: Option<Unit>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Serialize)]
+struct Weather {
+    location: String,
+    temperature: f32,
+    unit: String,
+}
+
+#[derive(Debug)]
+struct WeatherTool {}
+
+#[async_trait]
+impl TypedTool for WeatherTool {
+    type Args = WeatherArgs;
+    type Output = Weather;
+
+    fn name(&self) -> String {
+        "get_weather".to_string()
+    }
+
+    async fn call(&self, args: WeatherArgs) -> Result<Weather> {
+        let (temperature, unit) = match args.unit {
+            Some(Unit::Fahrenheit) => (68.0, "fahrenheit"),
+            _ => (20.0, "celsius"),
+        };
+        Ok(Weather {
+            location: args.location,
+            temperature,
+            unit: unit.to_string(),
+        })
+    }
+}
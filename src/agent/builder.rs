@@ -1,9 +1,23 @@
 use anyhow::{Context, Result};
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+use tracing::warn;
 
 use crate::{
-    agent::{completion::Agent, service::AgentService},
-    capabilities::{client::{completion::LlmClient, mcp::MCPServerAdapter, tool::Tool}, tools::mcp::MCPServerConfig},
+    agent::{
+        completion::{Agent, TruncationPolicy},
+        execution::{ArgumentValidation, ToolErrorPolicy, ToolExecutionConfig},
+        pricing::CostTracker,
+        service::AgentService,
+    },
+    capabilities::{
+        client::{completion::LlmClient, mcp::MCPServerAdapter, tool::Tool},
+        completion::request::{ConversationState, Reasoning, ReasoningEffort, ResponseFormat, Sampling},
+        tools::{
+            mcp::{MCPRegistry, MCPServerConfig},
+            tool::ToolRegistry,
+        },
+    },
+    http::config::ClientConfig,
     providers::{
         anthropic::{self, completion::AnthropicClient},
         gemini::{self, completion::GeminiClient},
@@ -13,6 +27,7 @@ use crate::{
 
 const MODEL_TEMPERATURE: f32 = 0.5;
 const MODEL_MAX_TOKENS: i32 = 5000;
+const STRUCTURED_OUTPUT_RETRIES: usize = 1;
 
 pub struct AgentBuilder<'a> {
     service: &'a AgentService,
@@ -21,11 +36,24 @@ pub struct AgentBuilder<'a> {
     client: Option<Arc<dyn LlmClient>>,
     temperature: Option<f32>,
     max_tokens: Option<i32>,
+    cost_tracker: Option<Arc<CostTracker>>,
+    truncation_policy: TruncationPolicy,
+    tool_registry: ToolRegistry,
+    mcp_registry: MCPRegistry,
+    allowed_tools: Option<Vec<String>>,
+    denied_tools: Vec<String>,
+    tool_execution: ToolExecutionConfig,
+    response_format: Option<ResponseFormat>,
+    structured_output_retries: usize,
+    conversation_state: ConversationState,
+    reasoning: Option<Reasoning>,
+    sampling: Sampling,
 }
 
 
 // AgentBuilder borrows immutably from AgentService using lifetime parameters, 
 // enabling multiple concurrent builders while mutating shared state through interior mutability (RwLock).
+// Tools attached by the builder belong to the agent, the service only holds the shared catalog.
 impl<'a> AgentBuilder<'a> {
     pub fn new(service: &'a AgentService) -> Self {
         Self {
@@ -35,61 +63,89 @@ impl<'a> AgentBuilder<'a> {
             client: None,
             temperature: None,
             max_tokens: None,
+            cost_tracker: None,
+            truncation_policy: TruncationPolicy::default(),
+            tool_registry: ToolRegistry::new(),
+            mcp_registry: MCPRegistry::new(),
+            allowed_tools: None,
+            denied_tools: Vec::new(),
+            tool_execution: ToolExecutionConfig::default(),
+            response_format: None,
+            structured_output_retries: STRUCTURED_OUTPUT_RETRIES,
+            conversation_state: ConversationState::default(),
+            reasoning: None,
+            sampling: Sampling::default(),
         }
     }
 
-    pub fn with_anthropic(mut self, api_key: &str) -> Result<Self> {
-        let mut clients = self.service.clients.write().unwrap();
-        self.llm = Some(anthropic::LLM.to_string());
-        self.model = Some(anthropic::MODEL_CLAUDE_SONNET_4_5.to_string());
-        let client_key = format! {"{}:{}", anthropic::LLM, anthropic::MODEL_CLAUDE_SONNET_4_5};
-        let client = clients
-            .entry(client_key)
-            .or_insert(self.anthropic_client(api_key)?);
-        self.client = Some(client.clone());
-        Ok(self)
+    pub fn with_anthropic(self, api_key: &str) -> Result<Self> {
+        self.with_llm(anthropic::LLM, anthropic::MODEL_CLAUDE_SONNET_4_5, api_key)
     }
 
-    fn anthropic_client(&self, api_key: &str) -> Result<Arc<dyn LlmClient>> {
-        let client = AnthropicClient::new(api_key.to_string())
-            .with_context(|| anyhow::anyhow!("Error creating Anthropic client"))?;
-        Ok(Arc::new(client))
+    pub fn with_openai(self, api_key: &str) -> Result<Self> {
+        self.with_llm(openai::LLM, openai::MODEL_GPT_5_NANO, api_key)
     }
 
-    pub fn with_openai(mut self, api_key: &str) -> Result<Self> {
-        let mut clients = self.service.clients.write().unwrap();
-        self.llm = Some(openai::LLM.to_string());
-        self.model = Some(openai::MODEL_GPT_5_NANO.to_string());
-        let client_key = format! {"{}:{}", openai::LLM, openai::MODEL_GPT_5_NANO};
-        let client = clients
-            .entry(client_key)
-            .or_insert(self.openai_client(api_key)?);
-        self.client = Some(client.clone());
-        Ok(self)
+    pub fn with_gemini(self, api_key: &str) -> Result<Self> {
+        self.with_llm(gemini::LLM, gemini::MODEL_GEMINI_3_FLASH_PREVIEW, api_key)
     }
 
-    fn openai_client(&self, api_key: &str) -> Result<Arc<dyn LlmClient>> {
-        let client = OpenAIClient::new(api_key.to_string())
-            .with_context(|| anyhow::anyhow!("Error creating Anthropic client"))?;
-        Ok(Arc::new(client))
+    // Set the provider and the model in one call.
+    // Takes the api key as well, the client of the provider is created from it or picked up
+    // from the service cache, with_model alone changes the model of an llm already set.
+    pub fn with_provider(self, llm: &str, api_key: &str, model: &str) -> Result<Self> {
+        self.with_model(model).with_llm(llm, model, api_key)
     }
 
-    pub fn with_gemini(mut self, api_key: &str) -> Result<Self> {
+    // set the model, overriding the provider default
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    // Sets the llm and picks up the client from the service cache.
+    // Clients are keyed by provider and api key so agents on different models share a client,
+    // and agents with different keys never do.
+    fn with_llm(mut self, llm: &str, default_model: &str, api_key: &str) -> Result<Self> {
         let mut clients = self.service.clients.write().unwrap();
-        self.llm = Some(gemini::LLM.to_string());
-        self.model = Some(gemini::MODEL_GEMINI_3_FLASH_PREVIEW.to_string());
-        let client_key = format! {"{}:{}", gemini::LLM, gemini::MODEL_GEMINI_3_FLASH_PREVIEW};
-        let client = clients
-            .entry(client_key)
-            .or_insert(self.gemini_client(api_key)?);
-        self.client = Some(client.clone());
+        let client_key = (llm.to_string(), api_key.to_string());
+        let client = match clients.get(&client_key) {
+            Some(client) => client.clone(),
+            None => {
+                let client = Self::create_client(llm, api_key, &self.service.client_config(llm))?;
+                clients.insert(client_key, client.clone());
+                client
+            }
+        };
+        drop(clients);
+
+        self.llm = Some(llm.to_string());
+        if self.model.is_none() {
+            self.model = Some(default_model.to_string());
+        }
+        self.client = Some(client);
         Ok(self)
     }
 
-    fn gemini_client(&self, api_key: &str) -> Result<Arc<dyn LlmClient>> {
-        let client = GeminiClient::new(api_key.to_string())
-            .with_context(|| anyhow::anyhow!("Error creating Anthropic client"))?;
-        Ok(Arc::new(client))
+    fn create_client(llm: &str, api_key: &str, config: &ClientConfig) -> Result<Arc<dyn LlmClient>> {
+        match llm {
+            anthropic::LLM => {
+                let client = AnthropicClient::with_config(api_key.to_string(), config)
+                    .with_context(|| anyhow::anyhow!("Error creating Anthropic client"))?;
+                Ok(Arc::new(client))
+            }
+            openai::LLM => {
+                let client = OpenAIClient::with_config(api_key.to_string(), config)
+                    .with_context(|| anyhow::anyhow!("Error creating OpenAI client"))?;
+                Ok(Arc::new(client))
+            }
+            gemini::LLM => {
+                let client = GeminiClient::with_config(api_key.to_string(), config)
+                    .with_context(|| anyhow::anyhow!("Error creating Gemini client"))?;
+                Ok(Arc::new(client))
+            }
+            _ => Err(anyhow::anyhow!("LLM '{}' is not supported", llm)),
+        }
     }
 
     //set the temperature
@@ -104,25 +160,206 @@ impl<'a> AgentBuilder<'a> {
         self
     }
 
-    pub fn with_tool<T: Tool + 'static>(self, tool: T) -> Self {
-        let mut registry = self.service.tool_registry.write().unwrap();
-        registry.register_tool(tool);
+    // set the sampling parameters, build fails when the provider does not support one
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.sampling = self.sampling.with_top_p(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.sampling = self.sampling.with_top_k(top_k);
+        self
+    }
+
+    pub fn with_stop_sequences(mut self, stop_sequences: &[&str]) -> Self {
+        self.sampling = self.sampling.with_stop_sequences(stop_sequences);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: i64) -> Self {
+        self.sampling = self.sampling.with_seed(seed);
+        self
+    }
+
+    // return the log probabilities of the output tokens with up to top_logprobs alternatives
+    pub fn with_logprobs(mut self, top_logprobs: u32) -> Self {
+        self.sampling = self.sampling.with_logprobs(top_logprobs);
+        self
+    }
+
+    // set how the tool loop handles output cut off by max_tokens
+    pub fn with_truncation_policy(mut self, truncation_policy: TruncationPolicy) -> Self {
+        self.truncation_policy = truncation_policy;
+        self
+    }
+
+    // set the thinking of reasoning models, by default the provider decides
+    pub fn with_reasoning(mut self, reasoning: Reasoning) -> Self {
+        self.reasoning = Some(reasoning);
+        self
+    }
+
+    // set the reasoning effort, keeping the other reasoning settings
+    pub fn with_reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning = Some(self.reasoning.unwrap_or_default().with_effort(effort));
+        self
+    }
+
+    // ask for the summaries of the thinking
+    pub fn with_reasoning_summary(mut self, summary: bool) -> Self {
+        self.reasoning = Some(self.reasoning.unwrap_or_default().with_summary(summary));
+        self
+    }
+
+    // set the thinking budget in tokens, used by Anthropic
+    pub fn with_thinking_budget(mut self, budget_tokens: u32) -> Self {
+        self.reasoning = Some(self.reasoning.unwrap_or_default().with_budget_tokens(budget_tokens));
+        self
+    }
+
+    // ask for json responses in the format, complete_structured sets the format of its type
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    // set the repair turns of complete_structured, 0 fails on the first invalid response
+    pub fn with_structured_output_retries(mut self, retries: usize) -> Self {
+        self.structured_output_retries = retries;
+        self
+    }
+
+    // set whether the provider keeps the history or every request sends the full history
+    pub fn with_conversation_state(mut self, conversation_state: ConversationState) -> Self {
+        self.conversation_state = conversation_state;
+        self
+    }
+
+    // share a cost tracker between agents, by default every agent tracks its own cost
+    pub fn with_cost_tracker(mut self, cost_tracker: Arc<CostTracker>) -> Self {
+        self.cost_tracker = Some(cost_tracker);
         self
     }
 
-    pub async fn with_mcp_registry<T: MCPServerAdapter + 'static>(self, config: MCPServerConfig, adapter: T) -> Result<Self> {
-        let mut registry = self.service.mcp_registry.write().unwrap();
-        let _ = registry.register_server_with_adapter(config, Box::new(adapter)).await?;
+    // attach a tool to the agent being built, other agents of the service do not see it
+    pub fn with_tool<T: Tool + 'static>(mut self, tool: T) -> Self {
+        self.tool_registry.register_tool(tool);
+        self
+    }
+
+    // attach a tool registered in the service catalog
+    pub fn with_shared_tool(mut self, name: &str) -> Result<Self> {
+        let tool = self
+            .service
+            .tool_registry
+            .read()
+            .unwrap()
+            .get_tool(name)
+            .ok_or_else(|| anyhow::anyhow!("Tool '{}' is not in the service catalog", name))?;
+        self.tool_registry.register_shared_tool(tool);
+        Ok(self)
+    }
+
+    // Connect a server with an adapter for the agent being built.
+    // Async because connecting runs the initialize handshake and lists the tools of the server.
+    pub async fn with_mcp_registry<T: MCPServerAdapter + 'static>(mut self, config: MCPServerConfig, adapter: T) -> Result<Self> {
+        let _ = self
+            .mcp_registry
+            .register_server_with_adapter(config, Box::new(adapter))
+            .await?;
+        Ok(self)
+    }
+
+    // connect a server speaking the standard MCP protocol for the agent being built
+    pub async fn with_mcp_server(mut self, config: MCPServerConfig) -> Result<Self> {
+        let _ = self.mcp_registry.register_server(config).await?;
+        Ok(self)
+    }
+
+    // Attach a tool of a server connected by the builder.
+    // Servers in the service catalog are shared without attaching their other tools.
+    pub async fn with_mcp_tool(mut self, server_name: &str, tool_name: &str) -> Result<Self> {
+        if !self.mcp_registry.has_server(server_name) {
+            let catalog = self.service.mcp_registry.read().unwrap();
+            let client = catalog.registry.get(server_name).cloned().ok_or_else(|| {
+                anyhow::anyhow!("Server '{}' has not been registered.", server_name)
+            })?;
+            drop(catalog);
+            self.mcp_registry.registry.insert(server_name.to_string(), client);
+        }
+        let _ = self.mcp_registry.register_tool(server_name, tool_name).await?;
         Ok(self)
     }
 
-    pub async fn with_mcp_tool(self, server_name: &str, tool_name: &str,) -> Result<Self>{
+    // attach a tool registered in the service catalog, named server___tool
+    pub fn with_shared_mcp_tool(mut self, name: &str) -> Result<Self> {
+        let catalog = self.service.mcp_registry.read().unwrap();
+        self.mcp_registry.share_tool(&catalog, name)?;
+        drop(catalog);
+        Ok(self)
+    }
 
-        let mut registry = self.service.mcp_registry.write().unwrap();
-        let _ = registry.register_tool(server_name, tool_name).await?;
+    // attach a server of the service catalog with all the tools registered for it
+    pub fn with_shared_mcp_server(mut self, server_name: &str) -> Result<Self> {
+        let catalog = self.service.mcp_registry.read().unwrap();
+        self.mcp_registry.share_server(&catalog, server_name)?;
+        drop(catalog);
         Ok(self)
     }
 
+    // Only keep the tools in the list when the agent is built.
+    // Names are tool names or server___tool for mcp tools, a trailing * matches a prefix.
+    pub fn with_allowed_tools(mut self, names: &[&str]) -> Self {
+        self.allowed_tools = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    // drop the tools in the list when the agent is built, the deny list wins over the allow list
+    pub fn with_denied_tools(mut self, names: &[&str]) -> Self {
+        self.denied_tools.extend(names.iter().map(|n| n.to_string()));
+        self
+    }
+
+    // set how many tool calls of a turn run at the same time
+    pub fn with_tool_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.tool_execution = self.tool_execution.with_max_concurrency(max_concurrency);
+        self
+    }
+
+    // set the timeout of every tool call
+    pub fn with_tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_execution = self.tool_execution.with_timeout(timeout);
+        self
+    }
+
+    // set the timeout of one tool, overriding the timeout of every tool call
+    pub fn with_tool_timeout_for(mut self, name: &str, timeout: Duration) -> Self {
+        self.tool_execution = self.tool_execution.with_tool_timeout(name, timeout);
+        self
+    }
+
+    // set whether tool failures fail the run or are sent back to the model
+    pub fn with_tool_error_policy(mut self, error_policy: ToolErrorPolicy) -> Self {
+        self.tool_execution = self.tool_execution.with_error_policy(error_policy);
+        self
+    }
+
+    // set how the arguments of tool calls are checked against the tool schemas
+    pub fn with_argument_validation(mut self, argument_validation: ArgumentValidation) -> Self {
+        self.tool_execution = self.tool_execution.with_argument_validation(argument_validation);
+        self
+    }
+
+    pub fn with_tool_execution(mut self, tool_execution: ToolExecutionConfig) -> Self {
+        self.tool_execution = tool_execution;
+        self
+    }
+
     //build the agent and take ownership
     pub fn build(self) -> Result<Agent> {
         let client = self
@@ -132,13 +369,50 @@ impl<'a> AgentBuilder<'a> {
         let model = self
             .model
             .ok_or_else(|| anyhow::anyhow!("Model is required"))?;
+        // fail early on sampling parameters the provider or model does not support
+        match llm.as_str() {
+            anthropic::LLM => {
+                anthropic::check_sampling(&model, &self.sampling)?;
+                let thinking = self
+                    .reasoning
+                    .as_ref()
+                    .is_some_and(|r| r.budget_tokens.is_some() || r.effort.is_some());
+                if self.temperature.is_some() && thinking {
+                    warn!("Anthropic extended thinking only samples with the default temperature, the temperature is ignored");
+                }
+            }
+            openai::LLM => {
+                openai::check_sampling(&model, &self.sampling)?;
+                if self.temperature.is_some() && openai::is_reasoning_model(&model) {
+                    warn!("Model {} only samples with its default temperature, the temperature is ignored", model);
+                }
+            }
+            gemini::LLM => gemini::check_sampling(&model, &self.sampling)?,
+            _ => {}
+        }
         let temperature: f32 = self.temperature.unwrap_or(MODEL_TEMPERATURE);
         let max_tokens = self.max_tokens.unwrap_or(MODEL_MAX_TOKENS);
-        let tool_guard = self.service.tool_registry.read().unwrap();
-        let tool_registry = tool_guard.clone().into();
 
-        let mcp_tool_guard = self.service.mcp_registry.read().unwrap();
-        let mcp_registry = Arc::new(mcp_tool_guard.clone());
+        let allowed_tools = self.allowed_tools;
+        let denied_tools = self.denied_tools;
+        let is_allowed = |name: &str| {
+            let allowed = allowed_tools
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|p| tool_matches(p, name)));
+            allowed && !denied_tools.iter().any(|p| tool_matches(p, name))
+        };
+        let mut tool_registry = self.tool_registry;
+        tool_registry.retain_tools(is_allowed);
+        let mut mcp_registry = self.mcp_registry;
+        mcp_registry.retain_tools(is_allowed);
+
+        let cost_tracker = match self.cost_tracker {
+            Some(cost_tracker) => cost_tracker,
+            None => {
+                let catalog = self.service.pricing_catalog.read().unwrap().clone();
+                Arc::new(CostTracker::new(Arc::new(catalog)))
+            }
+        };
 
         Ok(Agent {
             llm,
@@ -146,9 +420,45 @@ impl<'a> AgentBuilder<'a> {
             client,
             temperature,
             max_tokens,
-            tool_registry,
-            mcp_registry,
+            tool_registry: Arc::new(tool_registry),
+            mcp_registry: Arc::new(mcp_registry),
+            cost_tracker,
+            truncation_policy: self.truncation_policy,
+            tool_execution: self.tool_execution,
+            response_format: self.response_format,
+            structured_output_retries: self.structured_output_retries,
+            conversation_state: self.conversation_state,
+            reasoning: self.reasoning,
+            sampling: self.sampling,
         })
 
     }
 }
+
+// tool_matches matches a tool name against a name or a prefix ending with *
+fn tool_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::service::AgentService;
+
+    fn client(service: &AgentService, api_key: &str, model: &str) -> Arc<dyn LlmClient> {
+        let builder = service.builder().with_provider("OpenAI", api_key, model).unwrap();
+        builder.client.unwrap()
+    }
+
+    #[test]
+    fn clients_shared_per_api_key() {
+        let service = AgentService::new();
+        let first = client(&service, "key-1", "gpt-4o");
+        assert!(Arc::ptr_eq(&first, &client(&service, "key-1", "gpt-4o-mini")));
+        assert!(!Arc::ptr_eq(&first, &client(&service, "key-2", "gpt-4o")));
+        assert_eq!(service.clients.read().unwrap().len(), 2);
+    }
+}
@@ -1,21 +1,55 @@
 use std::sync::Arc;
 
-use crate::capabilities::{
-    client::completion::{CompletionStreamResponse, LlmClient},
-    completion::{
-        message::Message,
-        request::CompletionRequest,
-        response::{CompletionResponse, CompletionResponseContent},
+use crate::{
+    agent::{
+        execution::{execute_tool_calls, ToolExecutionConfig},
+        pricing::{CostSummary, CostTracker},
     },
-    tools::{
-        mcp::MCPRegistry,
-        tool::{ToolDefinition, ToolRegistry},
+    error::Error,
+    capabilities::{
+        client::completion::{CompletionStreamResponse, LlmClient},
+        completion::{
+            message::Message,
+            request::{CompletionRequest, ConversationState, Reasoning, ResponseFormat, Sampling},
+            response::{CompletionResponse, CompletionResponseContent, FinishReason, Usage},
+            stream::{CompletionStreamAggregator, CompletionStreamEvent},
+        },
+        tools::{
+            mcp::MCPRegistry,
+            request::ToolCallRequest,
+            schema,
+            tool::{ToolDefinition, ToolRegistry},
+        },
     },
 };
 use anyhow::Result;
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::debug;
 
-#[derive(Debug)]
+const MAX_ITERATIONS: usize = 5;
+const CONTINUE_PROMPT: &str =
+    "Your previous answer was cut off. Continue exactly where you stopped, without repeating any text.";
+const REPAIR_PROMPT: &str =
+    "Your previous answer did not match the required JSON schema. Respond again with only the corrected JSON. Errors:";
+
+// TruncationPolicy is how the tool loop handles a response cut off by max_tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TruncationPolicy {
+    // fail with Error::Truncated
+    #[default]
+    Error,
+    // ask the model to continue and join the text of the turns
+    Continue,
+    // return the truncated response
+    Ignore,
+}
+
+#[derive(Debug, Clone)]
 pub struct Agent {
     pub llm: String,
     pub model: String,
@@ -25,6 +59,16 @@ pub struct Agent {
     pub max_tokens: i32,
     pub tool_registry: Arc<ToolRegistry>,
     pub mcp_registry: Arc<MCPRegistry>,
+    pub cost_tracker: Arc<CostTracker>,
+    pub truncation_policy: TruncationPolicy,
+    pub tool_execution: ToolExecutionConfig,
+    // json format of every response, complete_structured sets its own
+    pub response_format: Option<ResponseFormat>,
+    // repair turns when a structured response does not match the schema
+    pub structured_output_retries: usize,
+    pub conversation_state: ConversationState,
+    pub reasoning: Option<Reasoning>,
+    pub sampling: Sampling,
 }
 
 impl Agent {
@@ -44,17 +88,11 @@ impl Agent {
     ) -> Result<CompletionResponse> {
         // debug!("Completion Request: {:#?}", request);
 
-        let request = CompletionRequest {
-            model: self.model.clone(),
-            system: system_prompt.clone(),
-            messages: messages.clone(),
-            temperature: self.temperature,
-            max_tokens: self.max_tokens,
-            stream: false,
-            definitions: Vec::new(),
-        };
+        let request = self.completion_request(system_prompt, messages, false, Vec::new());
 
-        self.client.complete(request).await
+        let response = self.client.complete(request).await?;
+        self.cost_tracker.record(&self.llm, &self.model, &response.usage);
+        Ok(response)
     }
 
     pub async fn complete_with_stream(
@@ -62,17 +100,65 @@ impl Agent {
         system_prompt: &Option<String>,
         messages: &Vec<Message>,
     ) -> Result<CompletionStreamResponse> {
-        let request = CompletionRequest {
-            model: self.model.clone(),
-            system: system_prompt.clone(),
-            messages: messages.clone(),
-            temperature: self.temperature,
-            max_tokens: self.max_tokens,
-            stream: true,
-            definitions: Vec::new(),
-        };
+        let request = self.completion_request(system_prompt, messages, true, Vec::new());
+
+        let stream = self.client.complete_with_stream(request).await?;
+        let cost_tracker = self.cost_tracker.clone();
+        let llm = self.llm.clone();
+        let model = self.model.clone();
+        let stream = stream.inspect(move |event| {
+            if let Ok(CompletionStreamEvent::Usage(usage)) = event {
+                cost_tracker.record(&llm, &model, usage);
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+
+    // complete_structured asks for a json response matching the schema of T and deserializes it.
+    // A response that does not parse or match the schema is sent back with the errors
+    // for up to structured_output_retries repair turns, then fails with Error::Decode.
+    pub async fn complete_structured<T: DeserializeOwned + JsonSchema>(
+        &self,
+        system_prompt: &Option<String>,
+        messages: &[Message],
+    ) -> Result<T> {
+        let format = ResponseFormat::for_type::<T>();
+        let schema = format.schema();
+        let mut request = self.completion_request(system_prompt, messages, false, Vec::new());
+        request.response_format = Some(format);
+
+        let mut attempt = 0;
+        loop {
+            let response = self.client.complete(request.clone()).await?;
+            debug!("CompletionResponse: {:#?}", response);
+            self.cost_tracker.record(&self.llm, &self.model, &response.usage);
+
+            // json cut off by max_tokens can not be repaired
+            if response.finish_reason == FinishReason::MaxTokens {
+                return Err(Error::Truncated {
+                    response: Box::new(response),
+                }
+                .into());
+            }
 
-        self.client.complete_with_stream(request).await
+            let text = response.text();
+            match parse_structured::<T>(&text, &schema) {
+                Ok(value) => return Ok(value),
+                Err(message) if attempt < self.structured_output_retries => {
+                    attempt += 1;
+                    debug!("Repairing structured response: {}", message);
+                    request.messages.push(Message::Assistant {
+                        content: text,
+                        response_id: response_id(&response.response_id),
+                    });
+                    request.messages.push(Message::User {
+                        content: format!("{} {}", REPAIR_PROMPT, message),
+                        response_id: None,
+                    });
+                }
+                Err(message) => return Err(Error::Decode { message }.into()),
+            }
+        }
     }
 
     pub async fn complete_with_tools(
@@ -80,34 +166,15 @@ impl Agent {
         system_prompt: &Option<String>,
         messages: &Vec<Message>,
     ) -> Result<CompletionResponse> {
-        let mut definitions: Vec<ToolDefinition> = self
-            .tool_registry
-            .get_tools()
-            // .cloned()
-            .iter()
-            .map(|e| ToolDefinition::from_tool(e.as_ref()))
-            .collect();
-        debug!("Tool_definitions: {:#?}", definitions);
+        let definitions = self.tool_definitions();
 
-        let mcp_definitions = self.mcp_registry.definitions.clone();
-        debug!("Mcp_definitions: {:#?}", mcp_definitions);
-        let _ = mcp_definitions
-            .iter()
-            .for_each(|e| definitions.push(e.1.clone()));
-        debug!("All definitions: {:#?}", definitions);
-
-        let request = CompletionRequest {
-            model: self.model.clone(),
-            system: system_prompt.clone(),
-            messages: messages.clone(),
-            temperature: self.temperature,
-            max_tokens: self.max_tokens,
-            stream: false,
-            definitions: definitions,
-        };
+        let request = self.completion_request(system_prompt, messages, false, definitions);
 
-        const MAX_ITERATIONS: usize = 5;
         let mut iteration = 0;
+        // usage of all the turns
+        let mut usage = Usage::default();
+        // text of the turns cut off by max_tokens
+        let mut continued_text = String::new();
 
         let mut nrequest = request;
         loop {
@@ -119,90 +186,597 @@ impl Agent {
             debug!("CompletionRequest: {:#?}", nrequest);
 
             let mut nmessages = Vec::new();
-            let response = self.client.complete(nrequest.clone()).await?;
+            let mut response = self.client.complete(nrequest.clone()).await?;
             debug!("CompletionResponse: {:#?}", response);
+            usage.add(&response.usage);
+            self.cost_tracker.record(&self.llm, &self.model, &response.usage);
 
-            // Check if response has tool calls
-            let has_tool_calls = response
-                .contents
-                .iter()
-                .any(|c| matches!(c, CompletionResponseContent::ToolCall { .. }));
+            if let Some(messages) = self.truncation_messages(&response)? {
+                continued_text.push_str(&response.text());
+                nrequest.messages.extend(messages);
+                continue;
+            }
 
-            if !has_tool_calls {
+            if !response.has_tool_calls() {
+                response.usage = usage;
+                if !continued_text.is_empty() {
+                    prepend_text(&mut response, &continued_text);
+                }
                 return Ok(response); // Done - return final answer
             }
 
-            for content in response.contents {
-                match content {
-                    CompletionResponseContent::Thought(text) => {
-                        debug!("Text: {}", text);
-                        let message = Message::Thought { content: text };
-                        nmessages.push(message);
-                    }
+            let (turn_messages, tool_call_requests) = turn_messages(response);
+            nmessages.extend(turn_messages);
+            let tool_messages = execute_tool_calls(
+                &self.tool_registry,
+                &self.mcp_registry,
+                &self.tool_execution,
+                &tool_call_requests,
+            )
+            .await?;
+            nmessages.extend(tool_messages);
 
-                    CompletionResponseContent::Text(text) => {
-                        debug!("Text: {}", text);
-                    }
-                    CompletionResponseContent::ToolCall(tool_call_request) => {
-                        let tool_option = self.tool_registry.get_tool(&tool_call_request.name);
-                        debug!("Tool Option: {:#?}", tool_option);
-                        let mut tool_found = false;
-                        match tool_option {
-                            Some(tool) => {
-                                let nmessage = Message::ToolCall {
-                                    call_id: tool_call_request.id.clone(),
-                                    arguments: tool_call_request.arguments.to_string(),
-                                    name: tool_call_request.name.clone(),
-                                };
-                                nmessages.push(nmessage);
-
-                                let response =
-                                    tool.execute(tool_call_request.arguments.clone()).await?;
-                                let nmessage = Message::ToolOutput {
-                                    call_id: tool_call_request.id.clone(),
-                                    output: response,
-                                    name: tool_call_request.name.clone(),
-                                };
-                                nmessages.push(nmessage);
-                                tool_found = true
-                            }
-                            None => {}
-                        }
+            // If there are toolcall and result messages add them to the next call
+            if !nmessages.is_empty() {
+                nrequest.messages.extend(nmessages);
+            }
+        }
+    }
+
+    // complete_with_tools_stream streams the turns of the tool loop.
+    // The events of every turn are passed on as they arrive, tool calls are
+    // collected from the streamed deltas and executed between turns.
+    // The usage summed over the turns and the stop event of the last turn are sent at the end.
+    pub async fn complete_with_tools_stream(
+        &self,
+        system_prompt: &Option<String>,
+        messages: &[Message],
+    ) -> Result<CompletionStreamResponse> {
+        let request = self.completion_request(system_prompt, messages, true, self.tool_definitions());
 
-                        if !tool_found {
-                            // Call the mcp tool
-                            debug!("Mcp tool_call: {:#?}", &tool_call_request.name);
-                            let response = self
-                                .mcp_registry
-                                .call_tool(
-                                    &tool_call_request.name,
-                                    tool_call_request.arguments.clone(),
-                                )
-                                .await?;
-                            debug!("Mcp tool_call Value: {:#?}", response);
-
-                            let nmessage = Message::ToolCall {
-                                call_id: tool_call_request.id.clone(),
-                                arguments: tool_call_request.arguments.to_string(),
-                                name: tool_call_request.name.clone(),
-                            };
-                            nmessages.push(nmessage);
-
-                            let nmessage = Message::ToolOutput {
-                                call_id: tool_call_request.id.clone(),
-                                output: response,
-                                name: tool_call_request.name.clone(),
-                            };
-                            nmessages.push(nmessage);
+        let agent = self.clone();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let result = agent.stream_with_tools(request, &sender).await;
+            if let Err(e) = result {
+                let _ = sender.send(Err(e));
+            }
+        });
+
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+
+    // cost returns the usage and cost recorded since the agent was built or last reset
+    pub fn cost(&self) -> CostSummary {
+        self.cost_tracker.summary()
+    }
+
+    pub fn reset_cost(&self) {
+        self.cost_tracker.reset();
+    }
+
+    // stream_with_tools runs the streaming tool loop and sends the events to the sender
+    async fn stream_with_tools(
+        &self,
+        mut request: CompletionRequest,
+        sender: &mpsc::UnboundedSender<Result<CompletionStreamEvent>>,
+    ) -> Result<()> {
+        let mut iteration = 0;
+        let mut usage = Usage::default();
+        loop {
+            iteration += 1;
+            if iteration > MAX_ITERATIONS {
+                return Err(anyhow::anyhow!("Max tool iterations exceeded"));
+            }
+
+            debug!("CompletionRequest: {:#?}", request);
+            let mut stream = self.client.complete_with_stream(request.clone()).await?;
+
+            let mut aggregator = CompletionStreamAggregator::new();
+            let mut stop = None;
+            while let Some(event) = stream.next().await {
+                let event = event?;
+                aggregator.push(&event);
+
+                match event {
+                    CompletionStreamEvent::Stop { .. } => stop = Some(event),
+                    CompletionStreamEvent::Usage(_) => {}
+                    _ => {
+                        if sender.send(Ok(event)).is_err() {
+                            // receiver dropped, nobody is listening anymore
+                            return Ok(());
                         }
                     }
                 }
             }
+            if let Some(turn_usage) = aggregator.usage() {
+                usage.add(turn_usage);
+                self.cost_tracker.record(&self.llm, &self.model, turn_usage);
+            }
 
-            // If there are toolcall and result messages add them to the next call
-            if nmessages.len() > 0 {
-                nrequest.messages.extend(nmessages);
+            // the error event has been passed on already
+            if aggregator.error().is_some() {
+                return Ok(());
+            }
+
+            let response = aggregator.finish()?;
+            if let Some(messages) = self.truncation_messages(&response)? {
+                // the text of the cut off turn has been streamed, the next turn continues it
+                request.messages.extend(messages);
+                continue;
+            }
+
+            if !response.has_tool_calls() {
+                let _ = sender.send(Ok(CompletionStreamEvent::Usage(usage)));
+                if let Some(event) = stop {
+                    let _ = sender.send(Ok(event));
+                }
+                return Ok(());
+            }
+
+            let (turn_messages, tool_call_requests) = turn_messages(response);
+            debug!("Streamed tool calls: {:#?}", tool_call_requests);
+            request.messages.extend(turn_messages);
+            let tool_messages = execute_tool_calls(
+                &self.tool_registry,
+                &self.mcp_registry,
+                &self.tool_execution,
+                &tool_call_requests,
+            )
+            .await?;
+            request.messages.extend(tool_messages);
+        }
+    }
+
+    // truncation_messages applies the truncation policy to a response cut off by max_tokens.
+    // Returns the messages that ask the model to continue, or None to go on with the response.
+    // Tool calls of a truncated response can not be trusted, they always fail.
+    fn truncation_messages(&self, response: &CompletionResponse) -> Result<Option<Vec<Message>>> {
+        if response.finish_reason != FinishReason::MaxTokens {
+            return Ok(None);
+        }
+        match self.truncation_policy {
+            TruncationPolicy::Ignore => Ok(None),
+            TruncationPolicy::Continue if !response.has_tool_calls() => Ok(Some(vec![
+                Message::Assistant {
+                    content: response.text(),
+                    response_id: response_id(&response.response_id),
+                },
+                Message::User {
+                    content: CONTINUE_PROMPT.to_string(),
+                    response_id: None,
+                },
+            ])),
+            _ => Err(Error::Truncated {
+                response: Box::new(response.clone()),
             }
+            .into()),
         }
     }
+
+    // completion_request builds the request with the settings of the agent
+    fn completion_request(
+        &self,
+        system_prompt: &Option<String>,
+        messages: &[Message],
+        stream: bool,
+        definitions: Vec<ToolDefinition>,
+    ) -> CompletionRequest {
+        CompletionRequest {
+            model: self.model.clone(),
+            system: system_prompt.clone(),
+            messages: messages.to_vec(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            stream,
+            definitions,
+            response_format: self.response_format.clone(),
+            conversation_state: self.conversation_state,
+            reasoning: self.reasoning.clone(),
+            sampling: self.sampling.clone(),
+        }
+    }
+
+    // tool_definitions returns the native tool definitions followed by the registered mcp tools
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<ToolDefinition> = self
+            .tool_registry
+            .get_tools()
+            // .cloned()
+            .iter()
+            .map(|e| ToolDefinition::from_tool(e.as_ref()))
+            .collect();
+        debug!("Tool_definitions: {:#?}", definitions);
+
+        let mcp_definitions = self.mcp_registry.definitions.clone();
+        debug!("Mcp_definitions: {:#?}", mcp_definitions);
+        let _ = mcp_definitions
+            .iter()
+            .for_each(|e| definitions.push(e.1.clone()));
+        debug!("All definitions: {:#?}", definitions);
+        definitions
+    }
+}
+
+// prepend_text joins the text of the truncated turns with the text of the final turn
+fn prepend_text(response: &mut CompletionResponse, text: &str) {
+    let position = response
+        .contents
+        .iter()
+        .position(|c| matches!(c, CompletionResponseContent::Text(_)));
+    match position {
+        Some(position) => {
+            if let CompletionResponseContent::Text(content) = &mut response.contents[position] {
+                content.insert_str(0, text);
+            }
+        }
+        None => response
+            .contents
+            .insert(0, CompletionResponseContent::Text(text.to_string())),
+    }
+}
+
+// turn_messages returns the thoughts and text of a tool turn as messages, in the order of
+// the response, and the tool calls to execute. The tool call messages follow them.
+// The last message of the turn is an assistant message with the response id, empty for a turn
+// ending without text, so the next request can refer to the response stored by the provider.
+fn turn_messages(response: CompletionResponse) -> (Vec<Message>, Vec<ToolCallRequest>) {
+    let mut messages = Vec::new();
+    let mut tool_call_requests = Vec::new();
+    for content in response.contents {
+        match content {
+            CompletionResponseContent::Thought {
+                text,
+                signature,
+                id,
+            } => {
+                debug!("Thought: {}", text);
+                messages.push(Message::Thought {
+                    content: text,
+                    signature,
+                    id,
+                });
+            }
+            CompletionResponseContent::RedactedThought { data } => {
+                messages.push(Message::RedactedThought { data });
+            }
+            CompletionResponseContent::Text(text) => {
+                debug!("Text: {}", text);
+                if !text.is_empty() {
+                    messages.push(Message::Assistant {
+                        content: text,
+                        response_id: None,
+                    });
+                }
+            }
+            CompletionResponseContent::ToolCall(tool_call_request) => {
+                tool_call_requests.push(tool_call_request);
+            }
+        }
+    }
+
+    let id = response_id(&response.response_id);
+    match messages.last_mut() {
+        Some(Message::Assistant { response_id, .. }) => *response_id = id,
+        _ => messages.push(Message::Assistant {
+            content: String::new(),
+            response_id: id,
+        }),
+    }
+    (messages, tool_call_requests)
+}
+
+// response_id is the id of a response, providers without one leave it empty
+fn response_id(id: &str) -> Option<String> {
+    Some(id.to_string()).filter(|id| !id.is_empty())
+}
+
+// parse_structured parses the json of a structured response, checks it against the schema
+// and deserializes it. Returns the errors to send back to the model.
+fn parse_structured<T: DeserializeOwned>(text: &str, schema: &Value) -> std::result::Result<T, String> {
+    // models sometimes wrap the json in a markdown code block
+    let text = text.trim();
+    let text = match text.strip_prefix("```") {
+        Some(fenced) => fenced
+            .split_once('\n')
+            .map(|(_, body)| body)
+            .unwrap_or_default()
+            .trim_end()
+            .trim_end_matches("```"),
+        None => text,
+    };
+
+    let mut value: Value = serde_json::from_str(text)
+        .map_err(|e| format!("the response is not valid JSON: {}", e))?;
+    // strict schemas make optional properties nullable, null means absent
+    remove_nulls(&mut value, schema);
+
+    let errors = schema::validate(schema, &value);
+    if !errors.is_empty() {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(errors.join("; "));
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+// remove_nulls drops the null properties the schema does not require,
+// a required nullable property keeps its null
+fn remove_nulls(value: &mut Value, schema: &Value) {
+    match value {
+        Value::Object(map) => {
+            let schema = object_schema(schema);
+            let required: Vec<&str> = schema
+                .get("required")
+                .and_then(|r| r.as_array())
+                .map(|r| r.iter().filter_map(|n| n.as_str()).collect())
+                .unwrap_or_default();
+            map.retain(|name, v| !v.is_null() || required.contains(&name.as_str()));
+            for (name, v) in map.iter_mut() {
+                remove_nulls(v, &schema["properties"][name]);
+            }
+        }
+        Value::Array(items) => {
+            let schema = &object_schema(schema)["items"];
+            items.iter_mut().for_each(|item| remove_nulls(item, schema));
+        }
+        _ => {}
+    }
+}
+
+// object_schema picks the variant of a nullable anyOf that describes the value
+fn object_schema(schema: &Value) -> &Value {
+    if schema.get("properties").is_some() || schema.get("items").is_some() {
+        return schema;
+    }
+    ["anyOf", "oneOf", "allOf"]
+        .iter()
+        .filter_map(|key| schema.get(key).and_then(|v| v.as_array()))
+        .flatten()
+        .find(|variant| variant.get("properties").is_some() || variant.get("items").is_some())
+        .unwrap_or(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex};
+
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{agent::service::AgentService, capabilities::client::tool::Tool};
+
+    // ScriptedClient streams the recorded events of one turn per request
+    #[derive(Debug, Default)]
+    struct ScriptedClient {
+        turns: Mutex<VecDeque<Vec<CompletionStreamEvent>>>,
+        requests: Mutex<Vec<CompletionRequest>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for ScriptedClient {
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            unimplemented!()
+        }
+
+        async fn complete_with_stream(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionStreamResponse> {
+            self.requests.lock().unwrap().push(request);
+            let events = self.turns.lock().unwrap().pop_front().unwrap_or_default();
+            Ok(Box::pin(futures::stream::iter(events.into_iter().map(Ok))))
+        }
+    }
+
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl Tool for Echo {
+        fn name(&self) -> String {
+            "echo".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Echo the text".to_string()
+        }
+
+        fn parameters(&self) -> Value {
+            json!({"type": "object", "properties": {"text": {"type": "string"}}, "required": ["text"]})
+        }
+
+        async fn execute(&self, value: Value) -> Result<Value> {
+            Ok(value["text"].clone())
+        }
+    }
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> CompletionStreamEvent {
+        CompletionStreamEvent::Usage(Usage {
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        })
+    }
+
+    fn stop(response_id: &str, reason: FinishReason) -> CompletionStreamEvent {
+        CompletionStreamEvent::Stop {
+            response_id: response_id.to_string(),
+            reason,
+        }
+    }
+
+    #[tokio::test]
+    async fn streamed_tool_loop() {
+        let client = Arc::new(ScriptedClient::default());
+        client.turns.lock().unwrap().extend([
+            // the arguments of the first call arrive as fragments, the second call sends
+            // its complete arguments twice like Gemini does
+            vec![
+                CompletionStreamEvent::TextDelta {
+                    text: "Echoing".to_string(),
+                },
+                CompletionStreamEvent::ToolCallStart {
+                    index: 1,
+                    id: "call-1".to_string(),
+                    name: "echo".to_string(),
+                },
+                CompletionStreamEvent::ToolCallArgsDelta {
+                    index: 1,
+                    arguments: r#"{"text":"#.to_string(),
+                },
+                CompletionStreamEvent::ToolCallArgsDelta {
+                    index: 1,
+                    arguments: r#""one"}"#.to_string(),
+                },
+                CompletionStreamEvent::ToolCallEnd { index: 1 },
+                CompletionStreamEvent::ToolCallStart {
+                    index: 2,
+                    id: "call-2".to_string(),
+                    name: "echo".to_string(),
+                },
+                CompletionStreamEvent::ToolCallArgs {
+                    index: 2,
+                    arguments: r#"{"text":"tw"}"#.to_string(),
+                },
+                CompletionStreamEvent::ToolCallArgs {
+                    index: 2,
+                    arguments: r#"{"text":"two"}"#.to_string(),
+                },
+                CompletionStreamEvent::ToolCallEnd { index: 2 },
+                usage(10, 5),
+                stop("response-1", FinishReason::ToolUse),
+            ],
+            vec![
+                CompletionStreamEvent::TextDelta {
+                    text: "Done".to_string(),
+                },
+                usage(20, 2),
+                stop("response-2", FinishReason::Stop),
+            ],
+        ]);
+        let service = AgentService::new();
+        let mut agent = service
+            .builder()
+            .with_provider("OpenAI", "key", "gpt-4o")
+            .unwrap()
+            .with_tool(Echo)
+            .build()
+            .unwrap();
+        agent.client = client.clone();
+
+        let messages = [Message::User {
+            content: "Echo one and two".to_string(),
+            response_id: None,
+        }];
+        let stream = agent
+            .complete_with_tools_stream(&None, &messages)
+            .await
+            .unwrap();
+        let events: Vec<CompletionStreamEvent> = stream.map(|e| e.unwrap()).collect().await;
+
+        // the turns are passed on, the summed usage and the last stop event come at the end
+        let text: String = events
+            .iter()
+            .filter_map(|e| match e {
+                CompletionStreamEvent::TextDelta { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "EchoingDone");
+        let [.., CompletionStreamEvent::Usage(usage), CompletionStreamEvent::Stop {
+            response_id,
+            reason,
+        }] = events.as_slice()
+        else {
+            panic!("unexpected events {:?}", events);
+        };
+        assert_eq!((usage.input_tokens, usage.output_tokens), (30, 7));
+        assert_eq!(
+            (response_id.as_str(), reason),
+            ("response-2", &FinishReason::Stop)
+        );
+
+        // the second turn sends the tool calls and their outputs
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let calls: Vec<(&str, &str)> = requests[1]
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::ToolCall {
+                    call_id, arguments, ..
+                } => Some((call_id.as_str(), arguments.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("call-1", r#"{"text":"one"}"#),
+                ("call-2", r#"{"text":"two"}"#)
+            ]
+        );
+        let outputs: Vec<&Value> = requests[1]
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::ToolOutput { output, .. } => Some(output),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(outputs, [&json!("one"), &json!("two")]);
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Quote {
+        symbol: String,
+        currency: Option<String>,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn nulls_kept_for_required_nullable_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "symbol": {"type": "string"},
+                "currency": {"type": ["string", "null"]},
+                "note": {"type": "string"},
+            },
+            "required": ["symbol", "currency"],
+        });
+        let text = r#"{"symbol": "AAPL", "currency": null, "note": null}"#;
+        let quote: Quote = parse_structured(text, &schema).unwrap();
+        assert_eq!(quote.symbol, "AAPL");
+        assert_eq!(quote.currency, None);
+        assert_eq!(quote.note, None);
+
+        // a required property that is not nullable still fails
+        let text = r#"{"symbol": null, "currency": "USD"}"#;
+        assert!(parse_structured::<Quote>(text, &schema).is_err());
+    }
+
+    #[test]
+    fn nulls_dropped_in_nested_nullable_objects() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "quotes": {
+                    "anyOf": [
+                        {"type": "array", "items": {
+                            "type": "object",
+                            "properties": {"symbol": {"type": "string"}, "note": {"type": "string"}},
+                            "required": ["symbol"],
+                        }},
+                        {"type": "null"},
+                    ]
+                },
+            },
+        });
+        let mut value = json!({"quotes": [{"symbol": "AAPL", "note": null}]});
+        remove_nulls(&mut value, &schema);
+        assert_eq!(value, json!({"quotes": [{"symbol": "AAPL"}]}));
+    }
 }
@@ -0,0 +1,485 @@
+use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use std::{collections::HashMap, time::Duration};
+use tracing::debug;
+
+use crate::{
+    capabilities::{
+        completion::message::Message,
+        tools::{
+            mcp::MCPRegistry,
+            request::ToolCallRequest,
+            schema::{self, ArgumentsError},
+            tool::ToolRegistry,
+        },
+    },
+    error::Error,
+};
+
+const TOOL_MAX_CONCURRENCY: usize = 4;
+
+// ToolErrorPolicy is what the tool loop does when a tool call fails,
+// the arguments are invalid or the model calls a tool the agent does not have
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ToolErrorPolicy {
+    // fail the run with Error::ToolExecution
+    #[default]
+    FailFast,
+    // send the error back as the tool output so the model can recover
+    SelfHeal,
+}
+
+// ArgumentValidation is how the arguments of a tool call are checked against the tool schema
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ArgumentValidation {
+    // pass the arguments to the tool unchecked
+    Off,
+    #[default]
+    Strict,
+    // fix numbers, booleans and arrays sent in the wrong shape, then validate
+    Coerce,
+}
+
+// ToolExecutionConfig is how the tool calls of one model turn are run.
+// Calls run concurrently up to max_concurrency, the outputs keep the order of the calls.
+#[derive(Debug, Clone)]
+pub struct ToolExecutionConfig {
+    // 1 runs the calls one after another
+    pub max_concurrency: usize,
+    // timeout of every call, tool_timeouts override it per tool
+    pub timeout: Option<Duration>,
+    pub tool_timeouts: HashMap<String, Duration>,
+    pub error_policy: ToolErrorPolicy,
+    pub argument_validation: ArgumentValidation,
+}
+
+impl Default for ToolExecutionConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: TOOL_MAX_CONCURRENCY,
+            timeout: None,
+            tool_timeouts: HashMap::new(),
+            error_policy: ToolErrorPolicy::default(),
+            argument_validation: ArgumentValidation::default(),
+        }
+    }
+}
+
+impl ToolExecutionConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // set the timeout of one tool, mcp tools are named server___tool
+    pub fn with_tool_timeout(mut self, name: &str, timeout: Duration) -> Self {
+        self.tool_timeouts.insert(name.to_string(), timeout);
+        self
+    }
+
+    pub fn with_error_policy(mut self, error_policy: ToolErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    pub fn with_argument_validation(mut self, argument_validation: ArgumentValidation) -> Self {
+        self.argument_validation = argument_validation;
+        self
+    }
+
+    pub fn timeout_for(&self, name: &str) -> Option<Duration> {
+        self.tool_timeouts.get(name).copied().or(self.timeout)
+    }
+}
+
+// execute_tool_calls runs the tool calls of a turn concurrently.
+// Returns the tool call messages followed by the tool output messages, both in the order
+// of the calls, so the calls of a turn and their outputs stay together in the history.
+// With the fail fast policy the first failed call fails the turn, the calls still running
+// are dropped and the pending ones are not started.
+pub(crate) async fn execute_tool_calls(
+    tool_registry: &ToolRegistry,
+    mcp_registry: &MCPRegistry,
+    config: &ToolExecutionConfig,
+    tool_call_requests: &[ToolCallRequest],
+) -> Result<Vec<Message>> {
+    // indices instead of references keep the future Send inside spawned streams
+    let results: Vec<(Message, Message)> = stream::iter(0..tool_call_requests.len())
+        .map(|i| Ok(execute_tool_call(tool_registry, mcp_registry, config, &tool_call_requests[i])))
+        .try_buffered(config.max_concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    let (mut tool_calls, tool_outputs): (Vec<Message>, Vec<Message>) = results.into_iter().unzip();
+    tool_calls.extend(tool_outputs);
+    Ok(tool_calls)
+}
+
+// execute_tool_call runs the native tool, or the mcp tool when there is no native tool with the name.
+// Only the tools attached to the agent can be called.
+// Returns the tool call and tool output messages for the next turn.
+async fn execute_tool_call(
+    tool_registry: &ToolRegistry,
+    mcp_registry: &MCPRegistry,
+    config: &ToolExecutionConfig,
+    tool_call_request: &ToolCallRequest,
+) -> Result<(Message, Message)> {
+    let name = &tool_call_request.name;
+    let result = match config.timeout_for(name) {
+        Some(timeout) => match tokio::time::timeout(
+            timeout,
+            call_tool(tool_registry, mcp_registry, config, tool_call_request),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("Timed out after {:?}", timeout)),
+        },
+        None => call_tool(tool_registry, mcp_registry, config, tool_call_request).await,
+    };
+    debug!("Tool_call result: {:#?}", result);
+
+    let (output, is_error) = match result {
+        Ok(output) => (output, false),
+        Err(e) if config.error_policy == ToolErrorPolicy::SelfHeal => {
+            let output = match e.downcast_ref::<ArgumentsError>() {
+                Some(arguments_error) => json!({
+                    "error": arguments_error.to_string(),
+                    "validation_errors": arguments_error.errors,
+                }),
+                None => json!({ "error": format!("{:#}", e) }),
+            };
+            (output, true)
+        }
+        Err(e) => {
+            return Err(Error::ToolExecution {
+                tool: name.clone(),
+                message: format!("{:#}", e),
+            }
+            .into())
+        }
+    };
+
+    // arguments that were not valid json are sent back as the model wrote them
+    let arguments = match &tool_call_request.arguments {
+        Value::String(arguments) => arguments.clone(),
+        arguments => arguments.to_string(),
+    };
+    Ok((
+        Message::ToolCall {
+            call_id: tool_call_request.id.clone(),
+            arguments,
+            name: name.clone(),
+        },
+        Message::ToolOutput {
+            call_id: tool_call_request.id.clone(),
+            output,
+            name: name.clone(),
+            is_error,
+        },
+    ))
+}
+
+// call_tool validates the arguments against the tool schema, or the inputSchema of mcp tools,
+// and calls the tool. MCP results flagged as errors are returned as errors.
+async fn call_tool(
+    tool_registry: &ToolRegistry,
+    mcp_registry: &MCPRegistry,
+    config: &ToolExecutionConfig,
+    tool_call_request: &ToolCallRequest,
+) -> Result<Value> {
+    let name = &tool_call_request.name;
+    let mut arguments = tool_call_request.arguments.clone();
+    if let Value::String(text) = &arguments {
+        return Err(anyhow::anyhow!("Arguments are not valid JSON: {}", text));
+    }
+    if !arguments.is_object() {
+        return Err(anyhow::anyhow!("Arguments must be a JSON object, got {}", arguments));
+    }
+
+    let tool = tool_registry.get_tool(name);
+    let parameters = match &tool {
+        Some(tool) => tool.parameters(),
+        None => match mcp_registry.definitions.get(name) {
+            Some(definition) => definition.parameters.clone(),
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Tool '{}' is not available to the agent",
+                    name
+                ))
+            }
+        },
+    };
+    validate_arguments(config.argument_validation, &parameters, &mut arguments)?;
+
+    if let Some(tool) = tool {
+        return tool.execute(arguments).await;
+    }
+
+    // Call the mcp tool
+    debug!("Mcp tool_call: {:#?}", name);
+    let response = mcp_registry.call_tool(name, arguments).await?;
+    if response.is_error {
+        return Err(anyhow::anyhow!("{}", response.into_value()));
+    }
+    Ok(response.into_value())
+}
+
+fn validate_arguments(
+    validation: ArgumentValidation,
+    parameters: &Value,
+    arguments: &mut Value,
+) -> Result<()> {
+    match validation {
+        ArgumentValidation::Off => return Ok(()),
+        ArgumentValidation::Coerce => schema::coerce(parameters, arguments),
+        ArgumentValidation::Strict => {}
+    }
+    let errors = schema::validate(parameters, arguments);
+    if !errors.is_empty() {
+        return Err(ArgumentsError { errors }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::capabilities::client::tool::Tool;
+
+    // Probe sleeps, then echoes its text or fails, and counts the calls running at once
+    #[derive(Debug, Default)]
+    struct Probe {
+        name: String,
+        delay: Duration,
+        fails: bool,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        completed: Arc<AtomicUsize>,
+    }
+
+    impl Probe {
+        fn new(name: &str, delay_ms: u64) -> Self {
+            Self {
+                name: name.to_string(),
+                delay: Duration::from_millis(delay_ms),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Tool for Probe {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn description(&self) -> String {
+            "Echo the text".to_string()
+        }
+
+        fn parameters(&self) -> Value {
+            json!({"type": "object", "properties": {"text": {"type": "string"}}, "required": ["text"]})
+        }
+
+        async fn execute(&self, value: Value) -> Result<Value> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.completed.fetch_add(1, Ordering::SeqCst);
+            if self.fails {
+                return Err(anyhow::anyhow!("{} failed", self.name));
+            }
+            Ok(value["text"].clone())
+        }
+    }
+
+    fn call(id: &str, name: &str, arguments: Value) -> ToolCallRequest {
+        ToolCallRequest {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    async fn execute(
+        tools: Vec<Probe>,
+        config: &ToolExecutionConfig,
+        calls: &[ToolCallRequest],
+    ) -> Result<Vec<Message>> {
+        let mut tool_registry = ToolRegistry::new();
+        tools
+            .into_iter()
+            .for_each(|tool| tool_registry.register_tool(tool));
+        execute_tool_calls(&tool_registry, &MCPRegistry::new(), config, calls).await
+    }
+
+    fn outputs(messages: &[Message]) -> Vec<(&str, &Value, bool)> {
+        messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::ToolOutput {
+                    call_id,
+                    output,
+                    is_error,
+                    ..
+                } => Some((call_id.as_str(), output, *is_error)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn outputs_keep_the_order_of_the_calls() {
+        let calls = [
+            call("1", "slow", json!({"text": "one"})),
+            call("2", "fast", json!({"text": "two"})),
+            call("3", "slow", json!({"text": "three"})),
+        ];
+        let tools = vec![Probe::new("slow", 50), Probe::new("fast", 0)];
+        let messages = execute(tools, &ToolExecutionConfig::new(), &calls)
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = messages
+            .iter()
+            .map(|m| match m {
+                Message::ToolCall { call_id, .. } | Message::ToolOutput { call_id, .. } => {
+                    call_id.as_str()
+                }
+                _ => panic!("unexpected message {:?}", m),
+            })
+            .collect();
+        assert_eq!(ids, ["1", "2", "3", "1", "2", "3"]);
+        assert_eq!(
+            outputs(&messages),
+            [
+                ("1", &json!("one"), false),
+                ("2", &json!("two"), false),
+                ("3", &json!("three"), false)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrency_limited() {
+        let probe = Probe::new("probe", 20);
+        let max_running = probe.max_running.clone();
+        let calls: Vec<ToolCallRequest> = (0..6)
+            .map(|i| call(&i.to_string(), "probe", json!({"text": "x"})))
+            .collect();
+        let config = ToolExecutionConfig::new().with_max_concurrency(2);
+        execute(vec![probe], &config, &calls).await.unwrap();
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+
+        let probe = Probe::new("probe", 20);
+        let max_running = probe.max_running.clone();
+        let config = ToolExecutionConfig::new().with_max_concurrency(1);
+        execute(vec![probe], &config, &calls).await.unwrap();
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn timeouts_per_tool() {
+        let calls = [
+            call("1", "slow", json!({"text": "one"})),
+            call("2", "fast", json!({"text": "two"})),
+        ];
+        let tools = || vec![Probe::new("slow", 500), Probe::new("fast", 50)];
+        let config = ToolExecutionConfig::new()
+            .with_timeout(Duration::from_millis(200))
+            .with_tool_timeout("slow", Duration::from_millis(20))
+            .with_error_policy(ToolErrorPolicy::SelfHeal);
+        let messages = execute(tools(), &config, &calls).await.unwrap();
+        assert_eq!(
+            outputs(&messages),
+            [
+                ("1", &json!({"error": "Timed out after 20ms"}), true),
+                ("2", &json!("two"), false)
+            ]
+        );
+
+        let config = config.with_error_policy(ToolErrorPolicy::FailFast);
+        let error = execute(tools(), &config, &calls).await.unwrap_err();
+        assert!(
+            matches!(error.downcast_ref::<Error>(), Some(Error::ToolExecution { tool, message })
+                if tool == "slow" && message == "Timed out after 20ms"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_fast_drops_the_other_calls() {
+        let failing = Probe {
+            fails: true,
+            ..Probe::new("failing", 10)
+        };
+        let slow = Probe::new("slow", 200);
+        let completed = slow.completed.clone();
+        let calls = [
+            call("1", "failing", json!({"text": "one"})),
+            call("2", "slow", json!({"text": "two"})),
+            call("3", "slow", json!({"text": "three"})),
+        ];
+        let config = ToolExecutionConfig::new().with_max_concurrency(2);
+        let error = execute(vec![failing, slow], &config, &calls)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("failing failed"), "{}", error);
+
+        // the running call was dropped and the pending one never started
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(completed.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn self_heal_reports_validation_errors() {
+        let calls = [
+            call("1", "probe", json!({"text": 5})),
+            call("2", "missing", json!({})),
+            call("3", "probe", Value::String("{\"text\":".to_string())),
+        ];
+        let config = ToolExecutionConfig::new().with_error_policy(ToolErrorPolicy::SelfHeal);
+        let messages = execute(vec![Probe::new("probe", 0)], &config, &calls)
+            .await
+            .unwrap();
+        let outputs = outputs(&messages);
+
+        let (_, output, is_error) = outputs[0];
+        assert!(is_error);
+        let errors = output["validation_errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["path"], "$.text");
+        assert!(output["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid arguments: $.text"));
+
+        // other failures only carry the error
+        assert_eq!(
+            outputs[1].1,
+            &json!({"error": "Tool 'missing' is not available to the agent"})
+        );
+        assert!(outputs[2].1.get("validation_errors").is_none());
+        assert!(outputs.iter().all(|(_, _, is_error)| *is_error));
+    }
+}
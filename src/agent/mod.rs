@@ -1,4 +1,6 @@
 pub mod builder;
 pub mod completion;
+pub mod execution;
+pub mod pricing;
 pub mod service;
 
@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    capabilities::completion::response::Usage,
+    providers::{anthropic, gemini, openai},
+};
+
+const TOKENS_PER_PRICE_UNIT: f64 = 1_000_000.0;
+
+// ModelPrice is the price of a model in USD per million tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default)]
+    pub cached_input: f64,
+    #[serde(default)]
+    pub cache_write: f64,
+}
+
+impl ModelPrice {
+    pub fn new(input: f64, output: f64, cached_input: f64, cache_write: f64) -> Self {
+        Self {
+            input,
+            output,
+            cached_input,
+            cache_write,
+        }
+    }
+
+    // cost returns the USD cost of the usage.
+    // Cached and cache creation tokens are part of the input tokens and are priced on their own.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let uncached_input = usage
+            .input_tokens
+            .saturating_sub(usage.cached_input_tokens)
+            .saturating_sub(usage.cache_creation_tokens);
+
+        (uncached_input as f64 * self.input
+            + usage.cached_input_tokens as f64 * self.cached_input
+            + usage.cache_creation_tokens as f64 * self.cache_write
+            + usage.output_tokens as f64 * self.output)
+            / TOKENS_PER_PRICE_UNIT
+    }
+}
+
+// PricingCatalog holds the model prices keyed by provider and model.
+// The built-in prices are the list prices at the time of writing, load_file overrides them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PricingCatalog {
+    prices: HashMap<String, HashMap<String, ModelPrice>>,
+}
+
+impl PricingCatalog {
+    pub fn new() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    pub fn with_defaults() -> Self {
+        let mut catalog = Self::new();
+        catalog.set_price(anthropic::LLM, anthropic::MODEL_CLAUDE_SONNET_4_5, ModelPrice::new(3.0, 15.0, 0.30, 3.75));
+        catalog.set_price(anthropic::LLM, anthropic::MODEL_CLAUDE_OPUS_4_5, ModelPrice::new(5.0, 25.0, 0.50, 6.25));
+        catalog.set_price(anthropic::LLM, anthropic::MODEL_CLAUDE_HAIKU_4_5, ModelPrice::new(1.0, 5.0, 0.10, 1.25));
+        catalog.set_price(openai::LLM, openai::MODEL_GPT_5, ModelPrice::new(1.25, 10.0, 0.125, 0.0));
+        catalog.set_price(openai::LLM, openai::MODEL_GPT_5_MINI, ModelPrice::new(0.25, 2.0, 0.025, 0.0));
+        catalog.set_price(openai::LLM, openai::MODEL_GPT_5_NANO, ModelPrice::new(0.05, 0.40, 0.005, 0.0));
+        catalog.set_price(gemini::LLM, gemini::MODEL_GEMINI_3_PRO_PREVIEW, ModelPrice::new(2.0, 12.0, 0.20, 0.0));
+        catalog.set_price(gemini::LLM, gemini::MODEL_GEMINI_3_FLASH_PREVIEW, ModelPrice::new(0.50, 3.0, 0.05, 0.0));
+        catalog.set_price(gemini::LLM, gemini::MODEL_GEMINI_2_5_FLASH, ModelPrice::new(0.30, 2.50, 0.03, 0.0));
+        catalog
+    }
+
+    // Load a json file of overrides, keyed by provider and model:
+    // { "OpenAI": { "gpt-5": { "input": 1.25, "output": 10.0, "cached_input": 0.125 } } }
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Error reading pricing file {}", path.display()))?;
+        let overrides: HashMap<String, HashMap<String, ModelPrice>> =
+            serde_json::from_str(&content)
+                .with_context(|| format!("Error parsing pricing file {}", path.display()))?;
+
+        for (llm, models) in overrides {
+            for (model, price) in models {
+                self.set_price(&llm, &model, price);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_price(&mut self, llm: &str, model: &str, price: ModelPrice) {
+        self.prices
+            .entry(llm.to_string())
+            .or_default()
+            .insert(model.to_string(), price);
+    }
+
+    // get_price matches the model exactly, then by the longest model prefix followed by a date
+    // so dated snapshots like gpt-5-mini-2025-08-07 use the price of gpt-5-mini.
+    // Other variants are models of their own, gpt-4o does not price gpt-4o-mini.
+    pub fn get_price(&self, llm: &str, model: &str) -> Option<&ModelPrice> {
+        let models = self.prices.get(llm)?;
+        if let Some(price) = models.get(model) {
+            return Some(price);
+        }
+        models
+            .iter()
+            .filter(|(name, _)| {
+                model
+                    .strip_prefix(name.as_str())
+                    .is_some_and(is_snapshot_suffix)
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| price)
+    }
+
+    // cost returns None when the model has no price
+    pub fn cost(&self, llm: &str, model: &str, usage: &Usage) -> Option<f64> {
+        self.get_price(llm, model).map(|price| price.cost(usage))
+    }
+}
+
+// is_snapshot_suffix matches the date of a snapshot, -2025-08-07, -20250929 or @20250929
+fn is_snapshot_suffix(suffix: &str) -> bool {
+    let Some(date) = suffix.strip_prefix('-').or_else(|| suffix.strip_prefix('@')) else {
+        return false;
+    };
+    date.chars().take_while(|c| c.is_ascii_digit()).count() >= 4
+}
+
+// CostSummary is the usage and cost recorded by a CostTracker
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CostSummary {
+    pub calls: u64,
+    pub usage: Usage,
+    pub cost: f64,
+    // calls to models without a price, their usage is counted but not their cost
+    pub unpriced_calls: u64,
+}
+
+// CostTracker sums the usage and cost of the completions of an agent session
+#[derive(Debug)]
+pub struct CostTracker {
+    catalog: Arc<PricingCatalog>,
+    summary: Mutex<CostSummary>,
+}
+
+impl CostTracker {
+    pub fn new(catalog: Arc<PricingCatalog>) -> Self {
+        Self {
+            catalog,
+            summary: Mutex::new(CostSummary::default()),
+        }
+    }
+
+    // record adds the usage of one completion and returns its cost
+    pub fn record(&self, llm: &str, model: &str, usage: &Usage) -> Option<f64> {
+        let cost = self.catalog.cost(llm, model, usage);
+        let mut summary = self.summary.lock().unwrap();
+        summary.calls += 1;
+        summary.usage.add(usage);
+        match cost {
+            Some(cost) => summary.cost += cost,
+            None => summary.unpriced_calls += 1,
+        }
+        cost
+    }
+
+    pub fn summary(&self) -> CostSummary {
+        self.summary.lock().unwrap().clone()
+    }
+
+    pub fn total_cost(&self) -> f64 {
+        self.summary.lock().unwrap().cost
+    }
+
+    pub fn reset(&self) {
+        *self.summary.lock().unwrap() = CostSummary::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snapshots_priced_by_longest_prefix() {
+        let mut catalog = PricingCatalog::new();
+        catalog.set_price(openai::LLM, "gpt-4o", ModelPrice::new(2.5, 10.0, 1.25, 0.0));
+        catalog.set_price(
+            openai::LLM,
+            "gpt-5",
+            ModelPrice::new(1.25, 10.0, 0.125, 0.0),
+        );
+        catalog.set_price(
+            openai::LLM,
+            "gpt-5-mini",
+            ModelPrice::new(0.25, 2.0, 0.025, 0.0),
+        );
+        catalog.set_price(
+            anthropic::LLM,
+            "claude-sonnet-4-5",
+            ModelPrice::new(3.0, 15.0, 0.30, 3.75),
+        );
+
+        let input = |llm: &str, model: &str| catalog.get_price(llm, model).map(|p| p.input);
+        assert_eq!(input(openai::LLM, "gpt-4o"), Some(2.5));
+        assert_eq!(input(openai::LLM, "gpt-4o-2024-08-06"), Some(2.5));
+        assert_eq!(input(openai::LLM, "gpt-4o-mini"), None);
+        assert_eq!(input(openai::LLM, "gpt-5-mini-2025-08-07"), Some(0.25));
+        assert_eq!(input(openai::LLM, "gpt-5-2025-08-07"), Some(1.25));
+        assert_eq!(input(openai::LLM, "gpt-5-nano"), None);
+        assert_eq!(
+            input(anthropic::LLM, "claude-sonnet-4-5-20250929"),
+            Some(3.0)
+        );
+        assert_eq!(
+            input(anthropic::LLM, "claude-sonnet-4-5@20250929"),
+            Some(3.0)
+        );
+        // the models of another provider are not matched
+        assert_eq!(input(gemini::LLM, "gpt-5"), None);
+    }
+
+    #[test]
+    fn load_file_overrides_prices() {
+        let path = std::env::temp_dir().join(format!("pricing-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"OpenAI": {"gpt-5": {"input": 1.0, "output": 8.0}, "gpt-4.1": {"input": 2.0, "output": 8.0, "cached_input": 0.5}}}"#,
+        )
+        .unwrap();
+        let mut catalog = PricingCatalog::with_defaults();
+        catalog.load_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            catalog.get_price(openai::LLM, "gpt-5"),
+            Some(&ModelPrice::new(1.0, 8.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            catalog.get_price(openai::LLM, "gpt-4.1"),
+            Some(&ModelPrice::new(2.0, 8.0, 0.5, 0.0))
+        );
+        // the other defaults are kept
+        assert_eq!(
+            catalog
+                .get_price(openai::LLM, openai::MODEL_GPT_5_MINI)
+                .map(|p| p.input),
+            Some(0.25)
+        );
+
+        assert!(catalog.load_file(&path).is_err());
+    }
+
+    #[test]
+    fn cached_and_cache_write_tokens_priced_apart() {
+        let price = ModelPrice::new(3.0, 15.0, 0.30, 3.75);
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 100_000,
+            cached_input_tokens: 400_000,
+            cache_creation_tokens: 200_000,
+            reasoning_tokens: 0,
+        };
+        // 400k uncached input, 400k cache reads, 200k cache writes and 100k output
+        let expected = 0.4 * 3.0 + 0.4 * 0.30 + 0.2 * 3.75 + 0.1 * 15.0;
+        assert!((price.cost(&usage) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tracker_sums_calls() {
+        let mut catalog = PricingCatalog::new();
+        catalog.set_price(openai::LLM, "gpt-5", ModelPrice::new(1.0, 10.0, 0.0, 0.0));
+        let tracker = CostTracker::new(Arc::new(catalog));
+
+        assert_eq!(
+            tracker.record(openai::LLM, "gpt-5", &usage(1_000_000, 0)),
+            Some(1.0)
+        );
+        assert_eq!(
+            tracker.record(openai::LLM, "gpt-5", &usage(0, 100_000)),
+            Some(1.0)
+        );
+        assert_eq!(
+            tracker.record(openai::LLM, "unknown", &usage(500, 50)),
+            None
+        );
+
+        let summary = tracker.summary();
+        assert_eq!(summary.calls, 3);
+        assert_eq!(summary.unpriced_calls, 1);
+        assert_eq!(summary.usage, usage(1_000_500, 100_050));
+        assert!((tracker.total_cost() - 2.0).abs() < 1e-9);
+
+        tracker.reset();
+        assert_eq!(tracker.summary().calls, 0);
+        assert_eq!(tracker.total_cost(), 0.0);
+    }
+}
@@ -1,15 +1,21 @@
+use anyhow::Result;
 use serde::Serialize;
 use std::{
     collections::HashMap,
+    path::Path,
     sync::{Arc, RwLock},
 };
 
 use crate::{
-    agent::builder::AgentBuilder,
+    agent::{builder::AgentBuilder, pricing::PricingCatalog},
     capabilities::{
-        client::completion::LlmClient,
-        tools::{mcp::MCPRegistry, tool::ToolRegistry},
+        client::{completion::LlmClient, mcp::MCPServerAdapter, tool::Tool},
+        tools::{
+            mcp::{MCPRegistry, MCPServerConfig},
+            tool::{ToolDefinition, ToolRegistry},
+        },
     },
+    http::{config::ClientConfig, retry::RetryPolicy},
     providers::{anthropic, gemini, openai},
 };
 
@@ -21,9 +27,18 @@ pub struct LlmProvider {
 }
 
 pub struct AgentService {
-    pub clients: Arc<RwLock<HashMap<String, Arc<dyn LlmClient>>>>,
+    // clients keyed by llm and api key
+    pub clients: Arc<RwLock<HashMap<(String, String), Arc<dyn LlmClient>>>>,
+    // catalog of tools and mcp servers the agents can share by name,
+    // an agent only sees the ones it opts in to with the builder
     pub tool_registry: Arc<RwLock<ToolRegistry>>,
     pub mcp_registry: Arc<RwLock<MCPRegistry>>,
+    pub pricing_catalog: Arc<RwLock<PricingCatalog>>,
+    // http configuration of the clients the service creates, provider_configs override it per llm
+    pub client_config: ClientConfig,
+    pub provider_configs: HashMap<String, ClientConfig>,
+    // applied over client_config and provider_configs, whichever order they were set in
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 impl AgentService {
@@ -32,9 +47,98 @@ impl AgentService {
             clients: Arc::new(RwLock::new(HashMap::new())),
             tool_registry: Arc::new(RwLock::new(ToolRegistry::new())),
             mcp_registry: Arc::new(RwLock::new(MCPRegistry::new())),
+            pricing_catalog: Arc::new(RwLock::new(PricingCatalog::with_defaults())),
+            client_config: ClientConfig::default(),
+            provider_configs: HashMap::new(),
+            retry_policy: None,
         }
     }
 
+    // set the retry policy of the clients the service creates
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    // set the http configuration of the clients the service creates
+    pub fn with_client_config(mut self, client_config: ClientConfig) -> Self {
+        self.client_config = client_config;
+        self
+    }
+
+    // set the http configuration for one llm, e.g. a gateway base url for OpenAI
+    pub fn with_provider_config(mut self, llm: &str, client_config: ClientConfig) -> Self {
+        self.provider_configs.insert(llm.to_string(), client_config);
+        self
+    }
+
+    // client_config returns the http configuration for the llm with the retry policy of the service
+    pub fn client_config(&self, llm: &str) -> ClientConfig {
+        let mut config = self
+            .provider_configs
+            .get(llm)
+            .unwrap_or(&self.client_config)
+            .clone();
+        if let Some(retry_policy) = &self.retry_policy {
+            config.retry_policy = retry_policy.clone();
+        }
+        config
+    }
+
+    // override the built-in model prices with the prices in the file
+    pub fn load_pricing_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.pricing_catalog.write().unwrap().load_file(path)
+    }
+
+    // register a tool in the service catalog, agents opt in with AgentBuilder::with_shared_tool
+    pub fn register_tool<T: Tool + 'static>(&self, tool: T) {
+        self.tool_registry.write().unwrap().register_tool(tool);
+    }
+
+    // connect a server speaking the standard MCP protocol and add it to the service catalog
+    pub async fn register_mcp_server(&self, config: MCPServerConfig) -> Result<Vec<ToolDefinition>> {
+        let server_name = config.name.clone();
+        let mut registry = MCPRegistry::new();
+        let definitions = registry.register_server(config).await?;
+        self.mcp_registry
+            .write()
+            .unwrap()
+            .share_server(&registry, &server_name)?;
+        Ok(definitions)
+    }
+
+    // connect a server with an adapter and add it to the service catalog
+    pub async fn register_mcp_server_with_adapter(
+        &self,
+        config: MCPServerConfig,
+        adapter: Box<dyn MCPServerAdapter>,
+    ) -> Result<Vec<ToolDefinition>> {
+        let server_name = config.name.clone();
+        let mut registry = MCPRegistry::new();
+        let definitions = registry
+            .register_server_with_adapter(config, adapter)
+            .await?;
+        self.mcp_registry
+            .write()
+            .unwrap()
+            .share_server(&registry, &server_name)?;
+        Ok(definitions)
+    }
+
+    // Register a tool of a catalog server so agents can share it as server___tool.
+    // The lock is not held while the server is called.
+    pub async fn register_mcp_tool(&self, server_name: &str, tool_name: &str) -> Result<ToolDefinition> {
+        let mut registry = MCPRegistry::new();
+        registry.share_server(&self.mcp_registry.read().unwrap(), server_name)?;
+        let definition = registry.register_tool(server_name, tool_name).await?;
+        self.mcp_registry
+            .write()
+            .unwrap()
+            .definitions
+            .insert(definition.name.clone(), definition.clone());
+        Ok(definition)
+    }
+
     pub fn builder(&self) -> AgentBuilder<'_> {
         AgentBuilder::new(self)
     }
@@ -46,17 +150,29 @@ impl AgentService {
         let gemini = LlmProvider {
             id: String::from(gemini::LLM.to_lowercase()),
             llm: gemini::LLM.to_string(),
-            models: vec![gemini::MODEL_GEMINI_3_FLASH_PREVIEW.to_string()],
+            models: vec![
+                gemini::MODEL_GEMINI_3_FLASH_PREVIEW.to_string(),
+                gemini::MODEL_GEMINI_3_PRO_PREVIEW.to_string(),
+                gemini::MODEL_GEMINI_2_5_FLASH.to_string(),
+            ],
         };
         let openai = LlmProvider {
             id: String::from(openai::LLM.to_lowercase()),
             llm: openai::LLM.to_string(),
-            models: vec![openai::MODEL_GPT_5_NANO.to_string()],
+            models: vec![
+                openai::MODEL_GPT_5_NANO.to_string(),
+                openai::MODEL_GPT_5_MINI.to_string(),
+                openai::MODEL_GPT_5.to_string(),
+            ],
         };
         let anthropic = LlmProvider {
             id: String::from(anthropic::LLM.to_lowercase()),
             llm: anthropic::LLM.to_string(),
-            models: vec![anthropic::MODEL_CLAUDE_SONNET_4_5.to_string()],
+            models: vec![
+                anthropic::MODEL_CLAUDE_SONNET_4_5.to_string(),
+                anthropic::MODEL_CLAUDE_OPUS_4_5.to_string(),
+                anthropic::MODEL_CLAUDE_HAIKU_4_5.to_string(),
+            ],
         };
 
         providers.push(gemini);
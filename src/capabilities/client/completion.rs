@@ -6,7 +6,8 @@ use futures_util::Stream;
 
 use crate::capabilities::completion::{
     request::CompletionRequest,
-    response::{CompletionChunkResponse, CompletionResponse},
+    response::CompletionResponse,
+    stream::CompletionStreamEvent,
 };
 
 // Llm config defines the unified set of parameters
@@ -14,7 +15,7 @@ pub struct LlmConfig {
     pub model: String,
 }
 
-pub type CompletionStreamResponse = Pin<Box<dyn Stream<Item = Result<CompletionChunkResponse>> + Send>>;
+pub type CompletionStreamResponse = Pin<Box<dyn Stream<Item = Result<CompletionStreamEvent>> + Send>>;
 
 #[async_trait]
 //LlmClient defines the trait for the llm
@@ -1,7 +1,9 @@
 use std::fmt::Debug;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
 use serde_json::Value;
 use anyhow::Result;
-use crate::capabilities::rcp::JsonRpcRequest;
+use crate::capabilities::rcp::{JsonRpcNotification, JsonRpcRequest};
 
 
 
@@ -13,3 +15,25 @@ pub trait MCPServerAdapter: Send + Sync + Debug {
     fn build_tool_call_request(&self, name: &str, params: Value) -> JsonRpcRequest;
     fn parse_tool_call_response(&self, text: String) -> Result<String>;
 }
+
+
+// MCPTransport moves JSON-RPC messages between the client and an MCP server
+#[async_trait]
+pub trait MCPTransport: Send + Sync + Debug {
+    // send_request sends the request and returns the matching JSON-RPC response message
+    async fn send_request(&self, request: &JsonRpcRequest) -> Result<Value>;
+    async fn send_notification(&self, notification: &JsonRpcNotification) -> Result<()>;
+
+    // called once the protocol version has been negotiated
+    fn set_protocol_version(&self, _version: &str) {}
+
+    // subscribe returns the notifications pushed by the server, if the transport delivers them
+    fn subscribe(&self) -> Option<broadcast::Receiver<Value>> {
+        None
+    }
+
+    // close shuts down the connection to the server
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
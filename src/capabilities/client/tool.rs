@@ -1,8 +1,12 @@
 use std::fmt::Debug;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
+use crate::capabilities::tools::schema::schema_for;
+
 
 #[async_trait]
 pub trait Tool: Send + Sync + Debug{
@@ -13,3 +17,130 @@ pub trait Tool: Send + Sync + Debug{
     async fn execute(&self, value: serde_json::Value) -> Result<Value>;
 }
 
+// TypedTool is a tool with typed arguments and output, every TypedTool is a Tool.
+// The parameters are the schema of Args, derived with schemars::JsonSchema so
+// the doc comments of the fields become their descriptions.
+#[async_trait]
+pub trait TypedTool: Send + Sync + Debug {
+    type Args: DeserializeOwned + JsonSchema + Send;
+    type Output: Serialize + Send;
+
+    fn name(&self) -> String;
+
+    // defaults to the doc comment of Args
+    fn description(&self) -> String {
+        schema_for::<Self::Args>()
+            .get("description")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output>;
+}
+
+#[async_trait]
+impl<T: TypedTool> Tool for T {
+    fn name(&self) -> String {
+        TypedTool::name(self)
+    }
+
+    fn description(&self) -> String {
+        TypedTool::description(self)
+    }
+
+    fn parameters(&self) -> Value {
+        let mut parameters = schema_for::<T::Args>();
+        // the description belongs to the tool
+        if let Value::Object(map) = &mut parameters {
+            map.remove("description");
+        }
+        parameters
+    }
+
+    async fn execute(&self, value: Value) -> Result<Value> {
+        let args: T::Args = serde_json::from_value(value)
+            .with_context(|| format!("Invalid arguments for tool {}", TypedTool::name(self)))?;
+        let output = self.call(args).await?;
+        serde_json::to_value(output)
+            .with_context(|| format!("Error serializing the output of tool {}", TypedTool::name(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Get the price of a stock
+    #[derive(Deserialize, JsonSchema)]
+    struct QuoteArgs {
+        /// Ticker symbol
+        symbol: String,
+        /// Days of history
+        days: Option<u32>,
+    }
+
+    #[derive(Serialize)]
+    struct Quote {
+        symbol: String,
+        prices: Vec<f64>,
+    }
+
+    #[derive(Debug)]
+    struct QuoteTool;
+
+    #[async_trait]
+    impl TypedTool for QuoteTool {
+        type Args = QuoteArgs;
+        type Output = Quote;
+
+        fn name(&self) -> String {
+            "quote".to_string()
+        }
+
+        async fn call(&self, args: QuoteArgs) -> Result<Quote> {
+            Ok(Quote {
+                symbol: args.symbol,
+                prices: vec![187.5; args.days.unwrap_or(1) as usize],
+            })
+        }
+    }
+
+    #[test]
+    fn parameters_from_args_schema() {
+        let tool: &dyn Tool = &QuoteTool;
+        assert_eq!(tool.name(), "quote");
+        assert_eq!(tool.description(), "Get the price of a stock");
+
+        let parameters = tool.parameters();
+        assert_eq!(parameters["type"], "object");
+        assert_eq!(
+            parameters["properties"]["symbol"],
+            json!({"type": "string", "description": "Ticker symbol"})
+        );
+        assert_eq!(parameters["properties"]["days"]["description"], "Days of history");
+        assert_eq!(parameters["required"], json!(["symbol"]));
+        // the description and title of Args are not part of the parameters
+        assert!(parameters.get("description").is_none());
+        assert!(parameters.get("title").is_none());
+    }
+
+    #[tokio::test]
+    async fn execute_deserializes_arguments() {
+        let tool: &dyn Tool = &QuoteTool;
+        let output = tool.execute(json!({"symbol": "AAPL", "days": 2})).await.unwrap();
+        assert_eq!(output, json!({"symbol": "AAPL", "prices": [187.5, 187.5]}));
+
+        for arguments in [json!({"symbol": 5}), json!({"days": 2}), json!("AAPL")] {
+            let error = tool.execute(arguments).await.unwrap_err();
+            assert!(
+                error.to_string().contains("Invalid arguments for tool quote"),
+                "{}",
+                error
+            );
+        }
+    }
+}
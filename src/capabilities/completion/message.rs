@@ -17,6 +17,15 @@ pub enum Message {
     },
     Thought {
         content: String,
+        #[serde(default)]
+        signature: Option<String>,
+        // id of the reasoning item, OpenAI needs it with the signature
+        #[serde(default)]
+        id: Option<String>,
+    },
+    // thinking the provider encrypted, sent back unchanged, Anthropic only
+    RedactedThought {
+        data: String,
     },
     ToolCall{
         arguments: String,
@@ -27,6 +36,37 @@ pub enum Message {
         call_id: String,
         output: Value,
         name: String,
+        // the tool failed, the output holds the error for the model
+        #[serde(default)]
+        is_error: bool,
     },
 }
 
+
+// new_turn splits the history for the providers that store the conversation.
+// Returns the id of the last response and the messages after it, without the thoughts and
+// tool calls of that response, which the provider keeps with it. Without a response id
+// the full history is returned.
+pub(crate) fn new_turn(messages: Vec<Message>) -> (Option<String>, Vec<Message>) {
+    let position = messages.iter().rposition(|message| {
+        matches!(message, Message::Assistant { response_id: Some(_), .. })
+    });
+    let Some(position) = position else {
+        return (None, messages);
+    };
+
+    let mut messages = messages.into_iter().skip(position);
+    let id = match messages.next() {
+        Some(Message::Assistant { response_id, .. }) => response_id,
+        _ => None,
+    };
+    let messages = messages
+        .filter(|message| {
+            !matches!(
+                message,
+                Message::Thought { .. } | Message::RedactedThought { .. } | Message::ToolCall { .. }
+            )
+        })
+        .collect();
+    (id, messages)
+}
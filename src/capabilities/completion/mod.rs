@@ -1,4 +1,5 @@
 pub mod request;
 pub mod response;
 pub mod message;
+pub mod stream;
 
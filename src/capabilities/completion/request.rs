@@ -1,4 +1,12 @@
-use crate::capabilities::{completion::message::Message, tools::tool::ToolDefinition};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::capabilities::{
+    completion::message::Message,
+    tools::{schema, tool::ToolDefinition},
+};
 
 #[derive(Debug, Clone)]
 pub struct CompletionRequest {
@@ -9,4 +17,324 @@ pub struct CompletionRequest {
     pub max_tokens: i32,
     pub stream: bool,
     pub definitions: Vec<ToolDefinition>,
+    pub response_format: Option<ResponseFormat>,
+    pub conversation_state: ConversationState,
+    // None keeps the reasoning defaults of the provider and model
+    pub reasoning: Option<Reasoning>,
+    pub sampling: Sampling,
+}
+
+// Sampling holds the sampling parameters besides temperature and max_tokens.
+// Unset parameters keep the provider defaults, a parameter the provider or the model
+// does not support fails the request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sampling {
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub stop_sequences: Vec<String>,
+    pub seed: Option<i64>,
+    // return the log probabilities of the output tokens with up to this many alternatives
+    pub logprobs: Option<u32>,
+}
+
+impl Sampling {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_stop_sequences(mut self, stop_sequences: &[&str]) -> Self {
+        self.stop_sequences = stop_sequences.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_logprobs(mut self, top_logprobs: u32) -> Self {
+        self.logprobs = Some(top_logprobs);
+        self
+    }
+
+    // parameters returns the names of the parameters that are set
+    pub fn parameters(&self) -> Vec<&'static str> {
+        let mut parameters = Vec::new();
+        if self.top_p.is_some() {
+            parameters.push("top_p");
+        }
+        if self.top_k.is_some() {
+            parameters.push("top_k");
+        }
+        if !self.stop_sequences.is_empty() {
+            parameters.push("stop_sequences");
+        }
+        if self.seed.is_some() {
+            parameters.push("seed");
+        }
+        if self.logprobs.is_some() {
+            parameters.push("logprobs");
+        }
+        parameters
+    }
+
+    // check fails with the parameters that are set and not supported
+    pub(crate) fn check(&self, target: &str, supported: &[&str]) -> Result<()> {
+        let unsupported: Vec<&str> = self
+            .parameters()
+            .into_iter()
+            .filter(|parameter| !supported.contains(parameter))
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} does not support the sampling parameters: {}",
+                target,
+                unsupported.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    // warn_ignored warns about and returns the parameters that are set and dropped from the request
+    pub(crate) fn warn_ignored(&self, target: &str, ignored: &[&str]) -> Vec<&'static str> {
+        let dropped: Vec<&'static str> = self
+            .parameters()
+            .into_iter()
+            .filter(|parameter| ignored.contains(parameter))
+            .collect();
+        if !dropped.is_empty() {
+            warn!(
+                "{} ignores the sampling parameters: {}",
+                target,
+                dropped.join(", ")
+            );
+        }
+        dropped
+    }
+}
+
+// ConversationState is where the history of a conversation is kept between requests.
+// Anthropic has no server side state, its requests always carry the full history.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ConversationState {
+    // the provider stores the responses, requests refer to the previous response by its id
+    #[default]
+    ServerManaged,
+    // nothing is stored by the provider, requests carry the full history in order
+    Stateless,
+}
+
+// ReasoningEffort is how much a reasoning model thinks before it answers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Minimal => "minimal",
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+// Reasoning is the thinking of reasoning models, mapped to the settings of every provider.
+// OpenAI and Gemini think by effort level, Anthropic by a token budget derived from the effort
+// when no budget is set. The thinking is returned as CompletionResponseContent::Thought.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reasoning {
+    pub effort: Option<ReasoningEffort>,
+    // max thinking tokens, only Anthropic takes a budget
+    pub budget_tokens: Option<u32>,
+    // return a summary of the thinking, Anthropic always returns it
+    pub summary: bool,
+}
+
+impl Reasoning {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.effort = Some(effort);
+        self
+    }
+
+    pub fn with_budget_tokens(mut self, budget_tokens: u32) -> Self {
+        self.budget_tokens = Some(budget_tokens);
+        self
+    }
+
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+}
+
+// ResponseFormat asks for a json response.
+// OpenAI and Gemini constrain the output natively, Anthropic is forced to call a tool
+// with the schema as input and the input is returned as the text of the response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseFormat {
+    // any json object
+    Json,
+    // json matching the schema, strict asks the provider to enforce it
+    JsonSchema {
+        name: String,
+        schema: Value,
+        strict: bool,
+    },
+}
+
+impl ResponseFormat {
+    // name is 1 to 64 letters, digits, _ or -, as OpenAI requires
+    pub fn json_schema(name: &str, schema: Value) -> Self {
+        let mut name: String = name
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                true => c,
+                false => '_',
+            })
+            .take(64)
+            .collect();
+        if name.is_empty() {
+            name = "response".to_string();
+        }
+        ResponseFormat::JsonSchema {
+            name,
+            schema: schema::portable(&schema),
+            strict: true,
+        }
+    }
+
+    // for_type builds the format from the schema of the type
+    pub fn for_type<T: JsonSchema>() -> Self {
+        Self::json_schema(&T::schema_name(), schema::schema_for::<T>())
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        if let ResponseFormat::JsonSchema { strict: s, .. } = &mut self {
+            *s = strict;
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ResponseFormat::Json => "json_response",
+            ResponseFormat::JsonSchema { name, .. } => name,
+        }
+    }
+
+    pub fn schema(&self) -> Value {
+        match self {
+            ResponseFormat::Json => json!({"type": "object", "additionalProperties": true}),
+            ResponseFormat::JsonSchema { schema, .. } => schema.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{anthropic, gemini, openai};
+
+    // one sampling per parameter, named as in the errors
+    fn samplings() -> Vec<(&'static str, Sampling)> {
+        vec![
+            ("top_p", Sampling::new().with_top_p(0.9)),
+            ("top_k", Sampling::new().with_top_k(40)),
+            (
+                "stop_sequences",
+                Sampling::new().with_stop_sequences(&["END"]),
+            ),
+            ("seed", Sampling::new().with_seed(7)),
+            ("logprobs", Sampling::new().with_logprobs(3)),
+        ]
+    }
+
+    #[test]
+    fn supported_parameters_per_provider() {
+        type Check = fn(&str, &Sampling) -> Result<()>;
+        let providers: [(&str, Check, &[&str]); 4] = [
+            (
+                "claude-haiku-4-5",
+                anthropic::check_sampling,
+                &["top_p", "top_k", "stop_sequences"],
+            ),
+            ("gpt-4.1", openai::check_sampling, &["top_p", "logprobs"]),
+            ("gpt-5", openai::check_sampling, &[]),
+            (
+                "gemini-2.5-flash",
+                gemini::check_sampling,
+                &["top_p", "stop_sequences", "seed"],
+            ),
+        ];
+        for (model, check, supported) in providers {
+            assert!(check(model, &Sampling::default()).is_ok(), "{}", model);
+            for (parameter, sampling) in samplings() {
+                let result = check(model, &sampling);
+                match supported.contains(&parameter) {
+                    true => assert!(result.is_ok(), "{} {}", model, parameter),
+                    false => {
+                        let error = result.unwrap_err().to_string();
+                        assert!(
+                            error.ends_with(&format!("sampling parameters: {}", parameter)),
+                            "{}",
+                            error
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_parameters_listed_together() {
+        let sampling = Sampling::new().with_top_k(40).with_seed(7).with_logprobs(3);
+        let error = openai::check_sampling("gpt-4.1", &sampling).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "OpenAI does not support the sampling parameters: top_k, seed"
+        );
+        let error = openai::check_sampling("o3-mini", &sampling).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "OpenAI model o3-mini does not support the sampling parameters: top_k, seed, logprobs"
+        );
+    }
+
+    #[test]
+    fn ignored_parameters() {
+        let sampling = Sampling::new()
+            .with_top_p(0.9)
+            .with_stop_sequences(&["END"])
+            .with_seed(7);
+        assert_eq!(
+            sampling.warn_ignored("Test", &["top_p", "top_k"]),
+            ["top_p"]
+        );
+        assert!(sampling
+            .warn_ignored("Test", &["top_k", "logprobs"])
+            .is_empty());
+        assert!(Sampling::default()
+            .warn_ignored("Test", &["top_p"])
+            .is_empty());
+    }
 }
@@ -7,54 +7,107 @@ use crate::capabilities::tools::request::ToolCallRequest;
 pub struct CompletionResponse {
     pub response_id: String,
     pub contents: Vec<CompletionResponseContent>,
+    pub usage: Usage,
+    pub finish_reason: FinishReason,
+    // log probabilities of the output tokens, when asked for with Sampling::logprobs
+    pub logprobs: Vec<TokenLogprob>,
+}
+
+// TokenLogprob is the log probability of an output token with the most likely alternatives
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+impl CompletionResponse {
+    // text joins the text contents
+    pub fn text(&self) -> String {
+        self.contents
+            .iter()
+            .filter_map(|c| match c {
+                CompletionResponseContent::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<&str>>()
+            .join("")
+    }
+
+    pub fn has_tool_calls(&self) -> bool {
+        self.contents
+            .iter()
+            .any(|c| matches!(c, CompletionResponseContent::ToolCall(_)))
+    }
 }
 
 
 #[derive(Debug, Clone)]
 pub enum CompletionResponseContent {
     Text(String),
-    Thought(String),
+    // signature is the opaque state the provider needs to get the thought back
+    Thought {
+        text: String,
+        signature: Option<String>,
+        id: Option<String>,
+    },
+    // thinking the provider encrypted, it has to be sent back with the turn
+    RedactedThought {
+        data: String,
+    },
     ToolCall(ToolCallRequest)
 }
 
 
-#[derive(Serialize, Debug, Clone, Deserialize)]
-pub struct CompletionChunkResponse {
-    pub response_id: String,
-    pub content: String,
-    pub thinking: String,
-    pub is_final: bool,
+// FinishReason is why the model stopped, normalized across the providers
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    // natural end of the turn
+    #[default]
+    Stop,
+    // output cut off by max_tokens
+    MaxTokens,
+    // the model asks for tool calls
+    ToolUse,
+    // a stop sequence was generated
+    StopSequence,
+    // refused or blocked by a safety filter
+    ContentFilter,
+    // any other provider reason
+    Other(String),
 }
 
+// Usage is the token usage of a completion, normalized across the providers.
+// input_tokens includes the cached and cache creation tokens,
+// output_tokens includes the reasoning tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub reasoning_tokens: u64,
+}
 
-
-impl CompletionChunkResponse {
-    
-    pub fn default() -> CompletionChunkResponse {
-        CompletionChunkResponse {
-            response_id: String::new(),
-            content: String::new(),
-            thinking: String::new(),
-            is_final: false,
-        }
-    }
-
-    pub fn stop(id: String) -> CompletionChunkResponse {
-        CompletionChunkResponse {
-            response_id: id,
-            content: String::new(),
-            thinking: String::new(),
-            is_final: true,
-        }
+impl Usage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
     }
 
-    pub fn content(content: String, thinking: String) -> CompletionChunkResponse {
-        CompletionChunkResponse {
-            response_id: String::new(),
-            content: content,
-            thinking: thinking,
-            is_final: false,
-        }
+    // add sums the usage of another completion, used across the turns of a tool loop
+    pub fn add(&mut self, usage: &Usage) {
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+        self.cached_input_tokens += usage.cached_input_tokens;
+        self.cache_creation_tokens += usage.cache_creation_tokens;
+        self.reasoning_tokens += usage.reasoning_tokens;
     }
-
 }
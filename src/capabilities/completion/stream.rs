@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    capabilities::{
+        client::completion::CompletionStreamResponse,
+        completion::response::{
+            CompletionResponse, CompletionResponseContent, FinishReason, TokenLogprob, Usage,
+        },
+        tools::request::ToolCallRequest,
+    },
+    error::Error,
+};
+
+// CompletionStreamEvent is one event of a streamed completion, normalized across the providers.
+// Tool calls are identified by their index in the response, the arguments arrive as json fragments
+// or, for providers that send them whole, as the complete arguments object.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompletionStreamEvent {
+    TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    // signature of the thinking block streamed before it
+    ThinkingSignature {
+        signature: String,
+        // id of the reasoning item the signature belongs to, OpenAI only
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    // encrypted thinking block, Anthropic only
+    RedactedThinking { data: String },
+    ToolCallStart { index: i32, id: String, name: String },
+    ToolCallArgsDelta { index: i32, arguments: String },
+    // complete arguments of the tool call, replaces the arguments received so far
+    ToolCallArgs { index: i32, arguments: String },
+    ToolCallEnd { index: i32 },
+    // log probabilities of the tokens of the text deltas
+    Logprobs { logprobs: Vec<TokenLogprob> },
+    Usage(Usage),
+    Stop { response_id: String, reason: FinishReason },
+    Error { message: String },
+}
+
+// event_stream flattens the events mapped from each SSE event into a CompletionStreamResponse
+pub(crate) fn event_stream<S>(stream: S) -> CompletionStreamResponse
+where
+    S: Stream<Item = Result<Vec<CompletionStreamEvent>>> + Send + 'static,
+{
+    let stream = stream.flat_map(|result| {
+        let events: Vec<Result<CompletionStreamEvent>> = match result {
+            Ok(events) => events.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        };
+        futures::stream::iter(events)
+    });
+    Box::pin(stream)
+}
+
+#[derive(Debug)]
+enum StreamBlock {
+    Text(String),
+    Thought {
+        text: String,
+        signature: Option<String>,
+        id: Option<String>,
+    },
+    RedactedThought(String),
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+}
+
+// CompletionStreamAggregator folds the events of a stream back into a CompletionResponse.
+// Content blocks keep the order in which they started.
+#[derive(Debug, Default)]
+pub struct CompletionStreamAggregator {
+    response_id: String,
+    blocks: Vec<StreamBlock>,
+    tool_calls: HashMap<i32, usize>,
+    usage: Option<Usage>,
+    finish_reason: FinishReason,
+    logprobs: Vec<TokenLogprob>,
+    error: Option<String>,
+}
+
+impl CompletionStreamAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: &CompletionStreamEvent) {
+        match event {
+            CompletionStreamEvent::TextDelta { text } => match self.blocks.last_mut() {
+                Some(StreamBlock::Text(content)) => content.push_str(text),
+                _ => self.blocks.push(StreamBlock::Text(text.clone())),
+            },
+            CompletionStreamEvent::ThinkingDelta { thinking } => match self.blocks.last_mut() {
+                Some(StreamBlock::Thought {
+                    text,
+                    signature: None,
+                    ..
+                }) => text.push_str(thinking),
+                _ => self.blocks.push(StreamBlock::Thought {
+                    text: thinking.clone(),
+                    signature: None,
+                    id: None,
+                }),
+            },
+            CompletionStreamEvent::ThinkingSignature { signature, id } => match self.blocks.last_mut() {
+                Some(StreamBlock::Thought {
+                    signature: s @ None,
+                    id: i,
+                    ..
+                }) => {
+                    *s = Some(signature.clone());
+                    *i = id.clone();
+                }
+                _ => self.blocks.push(StreamBlock::Thought {
+                    text: String::new(),
+                    signature: Some(signature.clone()),
+                    id: id.clone(),
+                }),
+            },
+            CompletionStreamEvent::RedactedThinking { data } => {
+                self.blocks.push(StreamBlock::RedactedThought(data.clone()))
+            }
+            CompletionStreamEvent::ToolCallStart { index, id, name } => {
+                self.tool_calls.insert(*index, self.blocks.len());
+                self.blocks.push(StreamBlock::ToolCall {
+                    id: id.clone(),
+                    name: name.clone(),
+                    arguments: String::new(),
+                });
+            }
+            CompletionStreamEvent::ToolCallArgsDelta { index, arguments } => {
+                if let Some(args) = self.tool_call_arguments(*index) {
+                    args.push_str(arguments);
+                }
+            }
+            CompletionStreamEvent::ToolCallArgs { index, arguments } => {
+                if let Some(args) = self.tool_call_arguments(*index) {
+                    *args = arguments.clone();
+                }
+            }
+            CompletionStreamEvent::ToolCallEnd { .. } => {}
+            CompletionStreamEvent::Logprobs { logprobs } => self.logprobs.extend_from_slice(logprobs),
+            CompletionStreamEvent::Usage(usage) => self.usage = Some(usage.clone()),
+            CompletionStreamEvent::Stop {
+                response_id,
+                reason,
+            } => {
+                self.response_id = response_id.clone();
+                self.finish_reason = reason.clone();
+            }
+            CompletionStreamEvent::Error { message } => self.error = Some(message.clone()),
+        }
+    }
+
+    // tool_call_arguments returns the arguments of the started tool call with the index
+    fn tool_call_arguments(&mut self, index: i32) -> Option<&mut String> {
+        let position = *self.tool_calls.get(&index)?;
+        match self.blocks.get_mut(position) {
+            Some(StreamBlock::ToolCall { arguments, .. }) => Some(arguments),
+            _ => None,
+        }
+    }
+
+    pub fn has_tool_calls(&self) -> bool {
+        !self.tool_calls.is_empty()
+    }
+
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    pub fn finish_reason(&self) -> &FinishReason {
+        &self.finish_reason
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    // finish returns the response, or the error the provider sent in the stream
+    pub fn finish(self) -> Result<CompletionResponse> {
+        if let Some(message) = self.error {
+            return Err(Error::Provider {
+                status: None,
+                message,
+            }
+            .into());
+        }
+
+        let mut contents = Vec::new();
+        for block in self.blocks {
+            let content = match block {
+                StreamBlock::Text(text) => CompletionResponseContent::Text(text),
+                StreamBlock::Thought {
+                    text,
+                    signature,
+                    id,
+                } => CompletionResponseContent::Thought {
+                    text,
+                    signature,
+                    id,
+                },
+                StreamBlock::RedactedThought(data) => {
+                    CompletionResponseContent::RedactedThought { data }
+                }
+                StreamBlock::ToolCall {
+                    id,
+                    name,
+                    arguments,
+                } => {
+                    // invalid json is kept as a string and reported back to the model
+                    let arguments: Value = if arguments.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&arguments).unwrap_or(Value::String(arguments))
+                    };
+                    CompletionResponseContent::ToolCall(ToolCallRequest {
+                        id,
+                        name,
+                        arguments,
+                    })
+                }
+            };
+            contents.push(content);
+        }
+
+        Ok(CompletionResponse {
+            response_id: self.response_id,
+            contents,
+            usage: self.usage.unwrap_or_default(),
+            finish_reason: self.finish_reason,
+            logprobs: self.logprobs,
+        })
+    }
+}
+
+// aggregate consumes the stream and returns the folded response
+pub async fn aggregate(mut stream: CompletionStreamResponse) -> Result<CompletionResponse> {
+    let mut aggregator = CompletionStreamAggregator::new();
+    while let Some(event) = stream.next().await {
+        aggregator.push(&event?);
+    }
+    aggregator.finish()
+}
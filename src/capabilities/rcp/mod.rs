@@ -1,12 +1,20 @@
+use anyhow::Result;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 
+// JSON-RPC error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
 
 #[derive(Debug, Serialize)]
 pub struct JsonRpcRequest {
     jsonrpc: String,
     method: String,
     id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
     params: Option<Value>
 }
 
@@ -21,16 +29,87 @@ impl JsonRpcRequest {
         Self { jsonrpc: String::from("2.0"), method: method, id: serde_json::from_str("1").unwrap(), params: params}
     }
 
+    // set_id replaces the id, used by clients that keep their own request counter
+    pub fn set_id(&mut self, id: Value) {
+        self.id = id;
+    }
+
+    pub fn id(&self) -> &Value {
+        &self.id
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+}
+
+
+// JsonRpcNotification is a request without an id, the receiver does not reply
+#[derive(Debug, Serialize)]
+pub struct JsonRpcNotification {
+    jsonrpc: String,
+    method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<Value>
+}
+
+impl JsonRpcNotification {
 
+    pub fn new(method: String, params: Option<Value>) -> Self {
+        Self { jsonrpc: String::from("2.0"), method, params }
+    }
 }
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct JsonRpcResponse<T> {
     #[allow(dead_code)]
     jsonrpc: String,
     #[allow(dead_code)]
     id: Value,
-    pub result: T
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
 }
 
+impl<T> JsonRpcResponse<T> {
+
+    pub fn success(id: Value, result: T) -> Self {
+        Self { jsonrpc: String::from("2.0"), id, result: Some(result), error: None }
+    }
+
+    pub fn failure(id: Value, error: JsonRpcError) -> Self {
+        Self { jsonrpc: String::from("2.0"), id, result: None, error: Some(error) }
+    }
+
+    // into_result returns the result or converts the error object into an error
+    pub fn into_result(self) -> Result<T> {
+        if let Some(error) = self.error {
+            return Err(anyhow::anyhow!(
+                "JSON-RPC error {}: {}",
+                error.code,
+                error.message
+            ));
+        }
+        self.result
+            .ok_or_else(|| anyhow::anyhow!("JSON-RPC response has neither result nor error"))
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+
+    pub fn new(code: i64, message: &str) -> Self {
+        Self { code, message: message.to_string(), data: None }
+    }
+}
@@ -1,30 +1,127 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
 use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
 use serde_json::{json, Value};
-use std::{collections::HashMap, sync::Arc};
-use tracing::debug;
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{broadcast, mpsc, oneshot},
+};
+use tracing::{debug, error};
 
 use crate::{
     capabilities::{
-        client::mcp::MCPServerAdapter,
-        rcp::{JsonRpcRequest, JsonRpcResponse},
+        client::mcp::{MCPServerAdapter, MCPTransport},
+        completion::message::Message,
+        rcp::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse},
         tools::{
-            request::{MCPToolCallParamsRequest, MCPToolGetParamsRequest, MCPToolListRequest},
+            request::{
+                MCPImplementation, MCPInitializeParamsRequest, MCPListParamsRequest,
+                MCPPromptGetParamsRequest, MCPResourceReadParamsRequest, MCPToolCallParamsRequest,
+            },
             response::{
-                MCPToolCallResponse, MCPToolGetDefinition, MCPToolListDefinition,
-                MCPToolListResponse,
+                MCPInitializeResponse, MCPProgress, MCPPrompt, MCPPromptGetResponse, MCPResource,
+                MCPResourceContents, MCPResourceReadResponse, MCPResourceTemplate,
+                MCPServerCapabilities, MCPServerInfo, MCPTool, MCPToolCallResponse,
+                MCPToolGetDefinition, MCPToolListDefinition,
             },
             tool::ToolDefinition,
         },
     },
-    http::HttpClient,
+    error::Error,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
 };
 
+pub const MCP_PROTOCOL_VERSION: &str = "2025-06-18";
+const MCP_SESSION_ID_HEADER: &str = "Mcp-Session-Id";
+const MCP_PROTOCOL_VERSION_HEADER: &str = "MCP-Protocol-Version";
+const MCP_STDIO_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+const MCP_SSE_MAX_RESUMPTIONS: usize = 3;
+const MCP_NOTIFICATION_CAPACITY: usize = 64;
+
+// MCPProgressSender receives the progress notifications of a running tool call
+pub type MCPProgressSender = mpsc::UnboundedSender<MCPProgress>;
+
 #[derive(Debug, Clone)]
 pub struct MCPServerConfig {
     pub name: String,
-    pub url: String,
-    pub api_key: String,
+    pub transport: MCPTransportConfig,
+}
+
+#[derive(Debug, Clone)]
+pub enum MCPTransportConfig {
+    // Streamable HTTP endpoint
+    Http {
+        url: String,
+        api_key: String,
+        client_config: Box<ClientConfig>,
+    },
+    // Local server launched as a subprocess, spoken to over stdin/stdout
+    Stdio {
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+    },
+}
+
+impl MCPServerConfig {
+    pub fn http(name: &str, url: &str, api_key: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transport: MCPTransportConfig::Http {
+                url: url.to_string(),
+                api_key: api_key.to_string(),
+                client_config: Box::default(),
+            },
+        }
+    }
+
+    pub fn stdio(name: &str, command: &str, args: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            transport: MCPTransportConfig::Stdio {
+                command: command.to_string(),
+                args: args.iter().map(|a| a.to_string()).collect(),
+                env: HashMap::new(),
+            },
+        }
+    }
+
+    // set the retry policy of the http transport, tools/call is only retried when the server rejected it
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        if let MCPTransportConfig::Http { client_config, .. } = &mut self.transport {
+            client_config.retry_policy = policy;
+        }
+        self
+    }
+
+    // set the http configuration of the http transport, the base url is not used
+    pub fn with_client_config(mut self, config: ClientConfig) -> Self {
+        if let MCPTransportConfig::Http { client_config, .. } = &mut self.transport {
+            **client_config = config;
+        }
+        self
+    }
+
+    // add an environment variable for the stdio server process
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        if let MCPTransportConfig::Stdio { env, .. } = &mut self.transport {
+            env.insert(key.to_string(), value.to_string());
+        }
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,36 +138,53 @@ impl MCPRegistry {
         }
     }
 
-    // register server with standard adapter
+    // register server speaking the standard MCP protocol
     pub async fn register_server(
         &mut self,
         config: MCPServerConfig,
     ) -> Result<Vec<ToolDefinition>> {
-        let adapter = Box::new(StandardAdapter {});
-        self.register_server_with_adapter(config, adapter).await
+        self.register_client(config, None).await
     }
 
-    // register server with an adapter
-    // Creates a new MCP client for the server
-    // Calls the tool_list method and returns a list of tools without the paramter definition.
+    // register server with an adapter for servers that wrap their tools in non standard calls
     pub async fn register_server_with_adapter(
         &mut self,
         config: MCPServerConfig,
         adapter: Box<dyn MCPServerAdapter>,
     ) -> Result<Vec<ToolDefinition>> {
-        // add code to check if server already inserted
+        self.register_client(config, Some(Arc::from(adapter))).await
+    }
+
+    // Creates a new MCP client for the server and runs the initialize handshake.
+    // Calls tools/list and returns the tools the server offers.
+    // A server name can only be registered once, the connected client is never replaced.
+    async fn register_client(
+        &mut self,
+        config: MCPServerConfig,
+        adapter: Option<Arc<dyn MCPServerAdapter>>,
+    ) -> Result<Vec<ToolDefinition>> {
+        if self.has_server(&config.name) {
+            return Err(anyhow::anyhow!(
+                "Server '{}' is already registered.",
+                config.name
+            ));
+        }
         let client =
             MCPClient::new(config.clone(), adapter).context("Error connecting the MCPClient")?;
-        let mcp_definitions = client.tool_list().await?;
-        // debug!("Definitions: {:#?}", mcp_definitions);
+        client
+            .initialize()
+            .await
+            .with_context(|| format!("Error initializing MCP server '{}'", config.name))?;
+
+        let mcp_tools = client.tool_list().await?;
         let mut definitions = Vec::new();
-        for mcp_definition in mcp_definitions {
-            let name = format!("{}___{}", config.name.clone(), mcp_definition.name);
+        for mcp_tool in mcp_tools {
+            let name = format!("{}___{}", config.name.clone(), mcp_tool.name);
             let definition = ToolDefinition::default_for_mcp(
                 "function",
                 &name,
-                &mcp_definition.description,
-                json!({}),
+                &mcp_tool.description,
+                mcp_tool.input_schema,
             );
             definitions.push(definition);
         }
@@ -86,192 +200,968 @@ impl MCPRegistry {
         server_name: &str,
         tool_name: &str,
     ) -> Result<ToolDefinition> {
-        if let Some(client) = self.registry.get(server_name) {
-            let mcp_get_definition = client.tool_get(&tool_name).await?;
-            let name = format!("{}___{}", server_name, tool_name);
+        let mcp_tool = self.client(server_name)?.tool_get(tool_name).await?;
+        let name = format!("{}___{}", server_name, tool_name);
 
-            let tool_definition = ToolDefinition::default_for_mcp(
-                "function",
-                &name,
-                &mcp_get_definition.description,
-                mcp_get_definition.parameters,
-            );
-            self.definitions.insert(name, tool_definition.clone());
+        let tool_definition = ToolDefinition::default_for_mcp(
+            "function",
+            &name,
+            &mcp_tool.description,
+            mcp_tool.input_schema,
+        );
+        self.definitions.insert(name, tool_definition.clone());
 
-            Ok(tool_definition)
-        } else {
-            return Err(anyhow::anyhow!(
-                "Server '{}' has not been registered.",
-                server_name
-            ));
-        }
+        Ok(tool_definition)
     }
 
     // Call the tool.
     // Split the tool_name to server and the tool_name
     // Call tool_call and return the result
-    pub async fn call_tool(&self, tool_name: &str, params: Value) -> Result<Value> {
-        let server: Vec<&str> = tool_name.split("___").collect();
-        // debug!("Server: {:#?}", server);
-        let server_name = server[0];
-        let tool_call_name = server[1];
-        if let Some(client) = self.registry.get(server_name) {
-            client.tool_call(tool_call_name, params).await
-        } else {
-            return Err(anyhow::anyhow!(
-                "Server '{}' has not been registered.",
-                server_name
-            ));
+    pub async fn call_tool(&self, tool_name: &str, params: Value) -> Result<MCPToolCallResponse> {
+        self.call_tool_with_progress(tool_name, params, None).await
+    }
+
+    // Call the tool and forward the progress notifications of the server to the sender
+    pub async fn call_tool_with_progress(
+        &self,
+        tool_name: &str,
+        params: Value,
+        progress: Option<MCPProgressSender>,
+    ) -> Result<MCPToolCallResponse> {
+        let (server_name, tool_call_name) = tool_name.split_once("___").ok_or_else(|| {
+            anyhow::anyhow!("'{}' is not an MCP tool name, expected server___tool", tool_name)
+        })?;
+        self.client(server_name)?
+            .tool_call(tool_call_name, params, progress)
+            .await
+    }
+
+    pub async fn list_resources(&self, server_name: &str) -> Result<Vec<MCPResource>> {
+        self.client(server_name)?.list_resources().await
+    }
+
+    pub async fn list_resource_templates(
+        &self,
+        server_name: &str,
+    ) -> Result<Vec<MCPResourceTemplate>> {
+        self.client(server_name)?.list_resource_templates().await
+    }
+
+    pub async fn read_resource(
+        &self,
+        server_name: &str,
+        uri: &str,
+    ) -> Result<Vec<MCPResourceContents>> {
+        self.client(server_name)?.read_resource(uri).await
+    }
+
+    pub async fn list_prompts(&self, server_name: &str) -> Result<Vec<MCPPrompt>> {
+        self.client(server_name)?.list_prompts().await
+    }
+
+    pub async fn get_prompt(
+        &self,
+        server_name: &str,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<MCPPromptGetResponse> {
+        self.client(server_name)?.get_prompt(name, arguments).await
+    }
+
+    // Read the resource and return it as a user message to add to the conversation
+    pub async fn resource_messages(&self, server_name: &str, uri: &str) -> Result<Vec<Message>> {
+        let contents = self.read_resource(server_name, uri).await?;
+        let messages = contents
+            .iter()
+            .map(|c| Message::User {
+                content: format!("Resource {}:\n{}", c.uri, c.to_text()),
+                response_id: None,
+            })
+            .collect();
+        Ok(messages)
+    }
+
+    // Get the prompt and return its messages to add to the conversation
+    pub async fn prompt_messages(
+        &self,
+        server_name: &str,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<Vec<Message>> {
+        let prompt = self.get_prompt(server_name, name, arguments).await?;
+        let messages = prompt
+            .messages
+            .iter()
+            .map(|m| match m.role.as_str() {
+                "assistant" => Message::Assistant {
+                    content: m.content.to_text(),
+                    response_id: None,
+                },
+                _ => Message::User {
+                    content: m.content.to_text(),
+                    response_id: None,
+                },
+            })
+            .collect();
+        Ok(messages)
+    }
+
+    // Share a server and its registered tools from another registry.
+    // The clients are clones, the connection to the server is shared.
+    pub fn share_server(&mut self, other: &MCPRegistry, server_name: &str) -> Result<()> {
+        let client = other.client(server_name)?;
+        self.registry.insert(server_name.to_string(), client.clone());
+        let prefix = format!("{}___", server_name);
+        for (name, definition) in other.definitions.iter() {
+            if name.starts_with(&prefix) {
+                self.definitions.insert(name.clone(), definition.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // Share a registered tool, named server___tool, and the connection to its server
+    pub fn share_tool(&mut self, other: &MCPRegistry, name: &str) -> Result<()> {
+        let definition = other
+            .definitions
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("MCP tool '{}' has not been registered.", name))?;
+        let server_name = name.split("___").next().unwrap_or_default();
+        let client = other.client(server_name)?;
+        self.registry
+            .entry(server_name.to_string())
+            .or_insert_with(|| client.clone());
+        self.definitions.insert(name.to_string(), definition.clone());
+        Ok(())
+    }
+
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    pub fn has_server(&self, server_name: &str) -> bool {
+        self.registry.contains_key(server_name)
+    }
+
+    // keep the registered tools whose name matches the predicate
+    pub fn retain_tools<F: Fn(&str) -> bool>(&mut self, f: F) {
+        self.definitions.retain(|name, _| f(name));
+    }
+
+    fn client(&self, server_name: &str) -> Result<&MCPClient> {
+        self.registry
+            .get(server_name)
+            .ok_or_else(|| anyhow::anyhow!("Server '{}' has not been registered.", server_name))
+    }
+
+    // Close the connections to all the servers.
+    // Stdio servers are also shut down when the last clone of the registry is dropped.
+    pub async fn close(&self) -> Result<()> {
+        for client in self.registry.values() {
+            client.transport.close().await?;
         }
+        Ok(())
     }
 }
 
+// MCPSession holds the state negotiated during initialize
+#[derive(Debug, Default)]
+pub struct MCPSession {
+    pub protocol_version: Option<String>,
+    pub capabilities: MCPServerCapabilities,
+    pub server_info: Option<MCPServerInfo>,
+}
+
 #[derive(Debug, Clone)]
 pub struct MCPClient {
     pub name: String,
-    pub url: String,
-    pub api_key: String,
-    http_client: HttpClient,
-    server_adapter: Arc<Box<dyn MCPServerAdapter>>,
+    transport: Arc<dyn MCPTransport>,
+    server_adapter: Option<Arc<dyn MCPServerAdapter>>,
+    next_id: Arc<AtomicI64>,
+    session: Arc<RwLock<MCPSession>>,
+    tools: Arc<RwLock<HashMap<String, MCPTool>>>,
 }
 
 impl MCPClient {
-    pub fn new(config: MCPServerConfig, adapter: Box<dyn MCPServerAdapter>) -> Result<Self> {
-        Ok(Self {
-            name: config.name,
-            url: config.url,
-            api_key: config.api_key,
-            http_client: HttpClient::new()?,
-            server_adapter: Arc::new(adapter),
-        })
+    pub fn new(
+        config: MCPServerConfig,
+        adapter: Option<Arc<dyn MCPServerAdapter>>,
+    ) -> Result<Self> {
+        let transport: Arc<dyn MCPTransport> = match config.transport {
+            MCPTransportConfig::Http {
+                url,
+                api_key,
+                client_config,
+            } => Arc::new(MCPHttpTransport::with_config(
+                &config.name,
+                url,
+                api_key,
+                &client_config,
+            )?),
+            MCPTransportConfig::Stdio { command, args, env } => {
+                Arc::new(MCPStdioTransport::new(&config.name, &command, &args, &env)?)
+            }
+        };
+        Ok(Self::with_transport(&config.name, transport, adapter))
     }
 
-    async fn tool_list(&self) -> Result<Vec<MCPToolListDefinition>> {
-        let request = self.server_adapter.build_tool_list_request();
-        let body = serde_json::json!(request);
-        let headers = self.get_header()?;
-        let response = self
-            .http_client
-            .post_request::<JsonRpcResponse<MCPToolListResponse>>(
-                self.url.clone(),
-                Some(headers),
-                body,
-            )
-            .await?;
+    // with_transport creates a client on a custom transport
+    pub fn with_transport(
+        name: &str,
+        transport: Arc<dyn MCPTransport>,
+        adapter: Option<Arc<dyn MCPServerAdapter>>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            transport,
+            server_adapter: adapter,
+            next_id: Arc::new(AtomicI64::new(1)),
+            session: Arc::new(RwLock::new(MCPSession::default())),
+            tools: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn capabilities(&self) -> MCPServerCapabilities {
+        self.session.read().unwrap().capabilities.clone()
+    }
 
-        let json_text = self
-            .server_adapter
-            .parse_tool_list_response(response.result.content[0].clone().text)
+    // initialize runs the handshake: initialize request followed by the initialized notification
+    pub async fn initialize(&self) -> Result<()> {
+        let params = MCPInitializeParamsRequest {
+            protocol_version: MCP_PROTOCOL_VERSION.to_string(),
+            capabilities: json!({}),
+            client_info: MCPImplementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        };
+        let request =
+            JsonRpcRequest::default("initialize".to_string(), serde_json::to_value(params).ok());
+        let response: MCPInitializeResponse = self.send_request(request).await?;
+        debug!("Initialize response: {:#?}", response);
+
+        self.transport.set_protocol_version(&response.protocol_version);
+        {
+            let mut session = self.session.write().unwrap();
+            session.protocol_version = Some(response.protocol_version);
+            session.capabilities = response.capabilities;
+            session.server_info = response.server_info;
+        }
+
+        let notification = JsonRpcNotification::new("notifications/initialized".to_string(), None);
+        self.transport.send_notification(&notification).await
+    }
+
+    async fn tool_list(&self) -> Result<Vec<MCPTool>> {
+        let tools = match &self.server_adapter {
+            Some(adapter) => self.adapter_tool_list(adapter.as_ref()).await?,
+            None => self.list_paginated("tools/list", "tools").await?,
+        };
+
+        let mut cache = self.tools.write().unwrap();
+        for tool in &tools {
+            cache.insert(tool.name.clone(), tool.clone());
+        }
+        Ok(tools)
+    }
+
+    async fn adapter_tool_list(&self, adapter: &dyn MCPServerAdapter) -> Result<Vec<MCPTool>> {
+        let request = adapter.build_tool_list_request();
+        let response: MCPToolCallResponse = self.send_request(request).await?;
+        let json_text = adapter
+            .parse_tool_list_response(response.text().unwrap_or_default())
             .with_context(|| "Server Adapter Tool List parsing error")?;
 
         // Parse JSON and extracts definitions
         let definitions: Vec<MCPToolListDefinition> = serde_json::from_str(&json_text)
             .map_err(|e| anyhow::anyhow!("Server Adapter error converting text {:#?}", e))?;
-        Ok(definitions)
+        let tools = definitions
+            .into_iter()
+            .map(|d| MCPTool {
+                name: d.name,
+                title: None,
+                description: d.description,
+                input_schema: json!({}),
+                output_schema: None,
+            })
+            .collect();
+        Ok(tools)
     }
 
-    async fn tool_get(&self, name: &str) -> Result<MCPToolGetDefinition> {
-        let request = self.server_adapter.build_tool_get_request(name);
-        let body = serde_json::json!(request);
-        let headers = self.get_header()?;
+    // tool_get returns the full definition of the tool.
+    // Standard servers return it with tools/list, adapters fetch it separately.
+    async fn tool_get(&self, name: &str) -> Result<MCPTool> {
+        if let Some(adapter) = &self.server_adapter {
+            return self.adapter_tool_get(adapter.as_ref(), name).await;
+        }
+
+        if let Some(tool) = self.tools.read().unwrap().get(name) {
+            return Ok(tool.clone());
+        }
+        self.tool_list()
+            .await?
+            .into_iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Tool '{}' not found on server '{}'", name, self.name))
+    }
+
+    async fn adapter_tool_get(&self, adapter: &dyn MCPServerAdapter, name: &str) -> Result<MCPTool> {
+        let request = adapter.build_tool_get_request(name);
         debug!("Tool_get request: {:#?}", request);
-        let response = self
-            .http_client
-            .post_request::<JsonRpcResponse<MCPToolListResponse>>(
-                self.url.clone(),
-                Some(headers),
-                body,
-            )
-            .await?;
+        let response: MCPToolCallResponse = self.send_request(request).await?;
 
-        let json_text = self
-            .server_adapter
-            .parse_tool_get_response(response.result.content[0].clone().text)
+        let json_text = adapter
+            .parse_tool_get_response(response.text().unwrap_or_default())
             .with_context(|| "Server Adapter Tool Get parsing error")?;
 
-        // debug!("Json Text: {:#?}", json_text);
         let definition: MCPToolGetDefinition = serde_json::from_str(&json_text)
             .map_err(|e| anyhow::anyhow!("Server Adapter error converting text {:#?}", e))?;
 
         debug!("tool_get Response: {:#?}", definition);
-        Ok(definition)
+        Ok(MCPTool {
+            name: name.to_string(),
+            title: None,
+            description: definition.description,
+            input_schema: definition.parameters,
+            output_schema: None,
+        })
     }
 
-    async fn tool_call(&self, name: &str, params: Value) -> Result<Value> {
-        let request = self.server_adapter.build_tool_call_request(name, params);
-        let body = serde_json::json!(request);
-        let headers = self.get_header()?;
+    async fn tool_call(
+        &self,
+        name: &str,
+        params: Value,
+        progress: Option<MCPProgressSender>,
+    ) -> Result<MCPToolCallResponse> {
+        // ask the server for progress notifications only when someone listens
+        let progress_token = progress
+            .as_ref()
+            .map(|_| json!(self.next_id.fetch_add(1, Ordering::SeqCst)));
+
+        let request = match &self.server_adapter {
+            Some(adapter) => adapter.build_tool_call_request(name, params),
+            None => {
+                let tool_call = MCPToolCallParamsRequest {
+                    name: name.to_string(),
+                    arguments: params,
+                    meta: progress_token
+                        .as_ref()
+                        .map(|token| json!({ "progressToken": token })),
+                };
+                JsonRpcRequest::default(
+                    "tools/call".to_string(),
+                    serde_json::to_value(tool_call).ok(),
+                )
+            }
+        };
         debug!("Tool_call request: {:#?}", request);
-        let response = self
-            .http_client
-            .post_request::<JsonRpcResponse<MCPToolCallResponse>>(
-                self.url.clone(),
-                Some(headers),
-                body,
-            )
-            .await?;
 
-        let json_text = self
-            .server_adapter
-            .parse_tool_call_response(response.result.content[0].clone().text)
-            .with_context(|| "Server Adapter Tool Get parsing error")?;
+        let notifications = self.transport.subscribe();
+        let response: MCPToolCallResponse = match (progress, progress_token, notifications) {
+            (Some(progress), Some(token), Some(mut notifications)) => {
+                let call = self.send_request(request);
+                tokio::pin!(call);
+                let response = loop {
+                    tokio::select! {
+                        response = &mut call => break response,
+                        notification = notifications.recv() => match notification {
+                            Ok(notification) => Self::forward_progress(&notification, &token, &progress),
+                            Err(broadcast::error::RecvError::Lagged(_)) => {}
+                            Err(broadcast::error::RecvError::Closed) => break call.await,
+                        }
+                    }
+                };
+                // notifications received just before the response
+                while let Ok(notification) = notifications.try_recv() {
+                    Self::forward_progress(&notification, &token, &progress);
+                }
+                response?
+            }
+            _ => self.send_request(request).await?,
+        };
+
+        let Some(adapter) = &self.server_adapter else {
+            debug!("tool_call Response: {:#?}", response);
+            return Ok(response);
+        };
+
+        let json_text = adapter
+            .parse_tool_call_response(response.text().unwrap_or_default())
+            .with_context(|| "Server Adapter Tool Call parsing error")?;
 
         debug!("tool_call Response: {:#?}", json_text);
 
         let value: Value = serde_json::from_str(&json_text)?;
+        Ok(MCPToolCallResponse {
+            content: response.content,
+            structured_content: Some(value),
+            is_error: response.is_error,
+        })
+    }
+
+    pub async fn list_resources(&self) -> Result<Vec<MCPResource>> {
+        self.require_capability("resources", self.capabilities().resources.is_some())?;
+        self.list_paginated("resources/list", "resources").await
+    }
+
+    pub async fn list_resource_templates(&self) -> Result<Vec<MCPResourceTemplate>> {
+        self.require_capability("resources", self.capabilities().resources.is_some())?;
+        self.list_paginated("resources/templates/list", "resourceTemplates")
+            .await
+    }
 
-        Ok(value)
+    pub async fn read_resource(&self, uri: &str) -> Result<Vec<MCPResourceContents>> {
+        self.require_capability("resources", self.capabilities().resources.is_some())?;
+        let params = MCPResourceReadParamsRequest {
+            uri: uri.to_string(),
+        };
+        let request = JsonRpcRequest::default(
+            "resources/read".to_string(),
+            serde_json::to_value(params).ok(),
+        );
+        let response: MCPResourceReadResponse = self.send_request(request).await?;
+        Ok(response.contents)
+    }
+
+    pub async fn list_prompts(&self) -> Result<Vec<MCPPrompt>> {
+        self.require_capability("prompts", self.capabilities().prompts.is_some())?;
+        self.list_paginated("prompts/list", "prompts").await
+    }
+
+    pub async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<MCPPromptGetResponse> {
+        self.require_capability("prompts", self.capabilities().prompts.is_some())?;
+        let params = MCPPromptGetParamsRequest {
+            name: name.to_string(),
+            arguments,
+        };
+        let request =
+            JsonRpcRequest::default("prompts/get".to_string(), serde_json::to_value(params).ok());
+        self.send_request(request).await
+    }
+
+    fn require_capability(&self, capability: &str, supported: bool) -> Result<()> {
+        if !supported {
+            return Err(anyhow::anyhow!(
+                "MCP server '{}' does not support {}",
+                self.name,
+                capability
+            ));
+        }
+        Ok(())
+    }
+
+    // list_paginated calls a list method until the server stops returning a cursor
+    // and collects the items of the field
+    async fn list_paginated<T: DeserializeOwned>(&self, method: &str, field: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = MCPListParamsRequest { cursor };
+            let request =
+                JsonRpcRequest::default(method.to_string(), serde_json::to_value(params).ok());
+            let mut response: Value = self.send_request(request).await?;
+
+            let page: Vec<T> = serde_json::from_value(response[field].take())
+                .map_err(|e| anyhow::anyhow!("Invalid {} response: {}", method, e))?;
+            items.extend(page);
+            match response["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
+        Ok(items)
+    }
+
+    fn forward_progress(notification: &Value, token: &Value, progress: &MCPProgressSender) {
+        if notification.get("method").and_then(|m| m.as_str()) != Some("notifications/progress") {
+            return;
+        }
+        let Some(params) = notification.get("params") else {
+            return;
+        };
+        match serde_json::from_value::<MCPProgress>(params.clone()) {
+            Ok(update) if &update.progress_token == token => {
+                let _ = progress.send(update);
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Invalid progress notification {}: {}", notification, e),
+        }
+    }
+
+    // send_request assigns the next id, sends the request on the transport and returns the result
+    async fn send_request<T: DeserializeOwned>(&self, mut request: JsonRpcRequest) -> Result<T> {
+        request.set_id(json!(self.next_id.fetch_add(1, Ordering::SeqCst)));
+        let value = self.transport.send_request(&request).await?;
+        let response: JsonRpcResponse<T> = serde_json::from_value(value.clone()).map_err(|e| {
+            anyhow::anyhow!("Failed to deserialize response: {}. Body: {}", e, value)
+        })?;
+        response.into_result()
+    }
+}
+
+// MCPHttpTransport implements the Streamable HTTP transport.
+// Every message is posted to the server endpoint, which answers with json or an event stream.
+#[derive(Debug)]
+pub struct MCPHttpTransport {
+    name: String,
+    url: String,
+    api_key: String,
+    http_client: HttpClient,
+    session_id: RwLock<Option<String>>,
+    protocol_version: RwLock<Option<String>>,
+    notifications: broadcast::Sender<Value>,
+}
+
+impl MCPHttpTransport {
+    pub fn new(name: &str, url: String, api_key: String) -> Result<Self> {
+        Self::with_config(name, url, api_key, &ClientConfig::default())
+    }
+
+    pub fn with_config(
+        name: &str,
+        url: String,
+        api_key: String,
+        config: &ClientConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            url,
+            api_key,
+            http_client: HttpClient::from_config(config)?,
+            session_id: RwLock::new(None),
+            protocol_version: RwLock::new(None),
+            notifications: broadcast::channel(MCP_NOTIFICATION_CAPACITY).0,
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
+
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.read().unwrap().clone()
+    }
+
+    // the server assigns the session id on initialize, it has to be echoed on every later request
+    fn store_session_id(&self, headers: &HeaderMap) {
+        if let Some(session_id) = headers
+            .get(MCP_SESSION_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+        {
+            *self.session_id.write().unwrap() = Some(session_id.to_string());
+        }
+    }
+
+    // Reads the event stream until the response to the request arrives.
+    // Server notifications and requests on the stream are handled on the way.
+    // If the stream drops before the response, it is resumed with Last-Event-ID.
+    async fn read_event_stream(
+        &self,
+        response: reqwest::Response,
+        request: &JsonRpcRequest,
+    ) -> Result<Value> {
+        let mut last_event_id: Option<String> = None;
+        let mut next_response = Some(response);
+        for _ in 0..=MCP_SSE_MAX_RESUMPTIONS {
+            let response = match next_response.take() {
+                Some(response) => response,
+                None => {
+                    let Some(event_id) = &last_event_id else {
+                        break;
+                    };
+                    debug!("Resuming MCP server '{}' stream after event {}", self.name, event_id);
+                    self.resume_event_stream(event_id).await?
+                }
+            };
+
+            let mut stream = response.bytes_stream().eventsource();
+            while let Some(event) = stream.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        debug!("MCP server '{}' stream error: {}", self.name, e);
+                        break;
+                    }
+                };
+                if !event.id.is_empty() {
+                    last_event_id = Some(event.id.clone());
+                }
+                if event.data.trim().is_empty() {
+                    continue;
+                }
+                let message: Value = serde_json::from_str(&event.data).map_err(|e| {
+                    anyhow::anyhow!("Failed to deserialize event: {}. Data: {}", e, event.data)
+                })?;
+
+                if message.get("method").is_none() && message.get("id") == Some(request.id()) {
+                    return Ok(message);
+                }
+                self.handle_server_message(message).await;
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "MCP server '{}' closed the event stream before answering {}",
+            self.name,
+            request.method()
+        ))
+    }
+
+    async fn resume_event_stream(&self, last_event_id: &str) -> Result<reqwest::Response> {
+        let mut headers = self.get_header()?;
+        headers.insert("Accept", "text/event-stream".parse()?);
+        headers.insert("Last-Event-ID", last_event_id.parse()?);
+        let response = self
+            .http_client
+            .get_raw_request(self.url.clone(), Some(headers))
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "MCP server '{}' returned {} resuming the event stream",
+                self.name,
+                response.status()
+            ));
+        }
+        Ok(response)
+    }
+
+    // notifications go to the subscribers, requests from the server are answered with a post
+    async fn handle_server_message(&self, message: Value) {
+        let id = message.get("id").cloned();
+        let method = message.get("method").and_then(|m| m.as_str());
+        match (id, method) {
+            (None, Some(_)) => {
+                let _ = self.notifications.send(message);
+            }
+            (Some(id), Some(method)) => {
+                let reply = server_request_reply(id, method);
+                let result = match self.get_header() {
+                    Ok(headers) => self
+                        .http_client
+                        .post_raw_request(self.url.clone(), Some(headers), reply, true)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error!("MCP server '{}' reply failed: {}", self.name, e);
+                }
+            }
+            _ => debug!("MCP server '{}' sent unexpected message: {}", self.name, message),
+        }
     }
 
     fn get_header(&self) -> Result<HeaderMap> {
         let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.api_key).parse()?);
+        if !self.api_key.is_empty() {
+            headers.insert("Authorization", format!("Bearer {}", self.api_key).parse()?);
+        }
+        headers.insert("Accept", "application/json, text/event-stream".parse()?);
+
+        if let Some(session_id) = self.session_id.read().unwrap().as_ref() {
+            headers.insert(MCP_SESSION_ID_HEADER, session_id.parse()?);
+        }
+        if let Some(version) = self.protocol_version.read().unwrap().as_ref() {
+            headers.insert(MCP_PROTOCOL_VERSION_HEADER, version.parse()?);
+        }
         Ok(headers)
     }
 }
 
+#[async_trait]
+impl MCPTransport for MCPHttpTransport {
+    async fn send_request(&self, request: &JsonRpcRequest) -> Result<Value> {
+        let body = serde_json::json!(request);
+        let headers = self.get_header()?;
+        // tool calls may have side effects, the other methods only read
+        let idempotent = request.method() != "tools/call";
+        let response = self
+            .http_client
+            .post_raw_request(self.url.clone(), Some(headers), body, idempotent)
+            .await?;
+        self.store_session_id(response.headers());
+
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if status.is_success() && content_type.starts_with("text/event-stream") {
+            return self.read_event_stream(response, request).await;
+        }
+        let headers = response.headers().clone();
+        let text = response.text().await.map_err(Error::from)?;
+        debug!("Raw response: {:#?}", text);
+
+        if !status.is_success() {
+            return Err(anyhow::Error::from(Error::from_http(status, &headers, &text)).context(
+                format!("MCP server '{}' failed {}", self.name, request.method()),
+            ));
+        }
+        serde_json::from_str(&text).map_err(|e| {
+            Error::Decode {
+                message: format!("Failed to deserialize response: {}. Body: {}", e, text),
+            }
+            .into()
+        })
+    }
+
+    async fn send_notification(&self, notification: &JsonRpcNotification) -> Result<()> {
+        let body = serde_json::json!(notification);
+        let headers = self.get_header()?;
+        let response = self
+            .http_client
+            .post_raw_request(self.url.clone(), Some(headers), body, true)
+            .await?;
+        self.store_session_id(response.headers());
+
+        if !response.status().is_success() {
+            let error = Error::from_response(response).await;
+            return Err(anyhow::Error::from(error)
+                .context(format!("MCP server '{}' failed notification", self.name)));
+        }
+        Ok(())
+    }
+
+    fn set_protocol_version(&self, version: &str) {
+        *self.protocol_version.write().unwrap() = Some(version.to_string());
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<Value>> {
+        Some(self.notifications.subscribe())
+    }
+}
+
+// server_request_reply answers a request sent by the server, only ping is supported
+fn server_request_reply(id: Value, method: &str) -> Value {
+    if method == "ping" {
+        json!({"jsonrpc": "2.0", "id": id, "result": {}})
+    } else {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32601, "message": format!("Method not found: {}", method)}
+        })
+    }
+}
+
+type MCPPendingRequests = Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>;
+
+// MCPStdioTransport launches the server as a subprocess and exchanges
+// newline delimited JSON-RPC messages over its stdin and stdout.
 #[derive(Debug)]
-struct StandardAdapter {}
+pub struct MCPStdioTransport {
+    name: String,
+    child: Mutex<Option<Child>>,
+    stdin: Arc<tokio::sync::Mutex<Option<ChildStdin>>>,
+    pending: MCPPendingRequests,
+    notifications: broadcast::Sender<Value>,
+}
 
-impl MCPServerAdapter for StandardAdapter {
-    fn build_tool_list_request(&self) -> JsonRpcRequest {
-        let request = JsonRpcRequest::default(
-            "tools/list".to_string(),
-            serde_json::to_value(MCPToolListRequest {}).ok(),
-        );
-        request
+impl MCPStdioTransport {
+    pub fn new(
+        name: &str,
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Error launching MCP server '{}' ({})", name, command))?;
+
+        let stdin = Arc::new(tokio::sync::Mutex::new(child.stdin.take()));
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("MCP server '{}' has no stdout", name))?;
+        let pending: MCPPendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let notifications = broadcast::channel(MCP_NOTIFICATION_CAPACITY).0;
+
+        // route responses to the waiting requests, answer server requests
+        let server_name = name.to_string();
+        let reader_pending = pending.clone();
+        let reader_stdin = stdin.clone();
+        let reader_notifications = notifications.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message: Value = match serde_json::from_str(&line) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        error!("MCP server '{}' sent invalid JSON: {} ({})", server_name, line, e);
+                        continue;
+                    }
+                };
+                Self::dispatch(
+                    &server_name,
+                    message,
+                    &reader_pending,
+                    &reader_stdin,
+                    &reader_notifications,
+                )
+                .await;
+            }
+            debug!("MCP server '{}' closed stdout", server_name);
+            // dropping the senders fails the requests still waiting
+            reader_pending.lock().unwrap().clear();
+        });
+
+        if let Some(stderr) = child.stderr.take() {
+            let server_name = name.to_string();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!("MCP server '{}' stderr: {}", server_name, line);
+                }
+            });
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            child: Mutex::new(Some(child)),
+            stdin,
+            pending,
+            notifications,
+        })
     }
 
-    fn parse_tool_list_response(&self, text: String) -> Result<String> {
-        Ok(text)
+    async fn dispatch(
+        name: &str,
+        message: Value,
+        pending: &MCPPendingRequests,
+        stdin: &tokio::sync::Mutex<Option<ChildStdin>>,
+        notifications: &broadcast::Sender<Value>,
+    ) {
+        let id = message.get("id").cloned();
+        let method = message.get("method").and_then(|m| m.as_str());
+        match (id, method) {
+            // response to one of our requests
+            (Some(id), None) => {
+                let sender = pending.lock().unwrap().remove(&id.to_string());
+                match sender {
+                    Some(sender) => {
+                        let _ = sender.send(message);
+                    }
+                    None => debug!("MCP server '{}' sent response for unknown id {}", name, id),
+                }
+            }
+            // request from the server
+            (Some(id), Some(method)) => {
+                let reply = server_request_reply(id, method);
+                if let Err(e) = Self::write_line(stdin, &reply).await {
+                    error!("MCP server '{}' reply failed: {}", name, e);
+                }
+            }
+            // notification from the server
+            (None, Some(method)) => {
+                debug!("MCP server '{}' notification: {}", name, method);
+                let _ = notifications.send(message);
+            }
+            (None, None) => debug!("MCP server '{}' sent unexpected message: {}", name, message),
+        }
     }
 
-    fn build_tool_get_request(&self, name: &str) -> JsonRpcRequest {
-        let tool_get = MCPToolGetParamsRequest {
-            tool_name: name.to_string(),
-        };
-        let request =
-            JsonRpcRequest::default("tools/get".to_string(), serde_json::to_value(tool_get).ok());
-        request
+    async fn write_line(
+        stdin: &tokio::sync::Mutex<Option<ChildStdin>>,
+        message: &Value,
+    ) -> Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        let mut guard = stdin.lock().await;
+        let stdin = guard
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("MCP server stdin is closed"))?;
+        stdin.write_all(line.as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MCPTransport for MCPStdioTransport {
+    async fn send_request(&self, request: &JsonRpcRequest) -> Result<Value> {
+        let (sender, receiver) = oneshot::channel();
+        let key = request.id().to_string();
+        self.pending.lock().unwrap().insert(key.clone(), sender);
+
+        if let Err(e) = Self::write_line(&self.stdin, &json!(request)).await {
+            self.pending.lock().unwrap().remove(&key);
+            return Err(e).with_context(|| format!("Error writing to MCP server '{}'", self.name));
+        }
+
+        receiver.await.map_err(|_| {
+            anyhow::anyhow!(
+                "MCP server '{}' exited before answering {}",
+                self.name,
+                request.method()
+            )
+        })
     }
-    fn parse_tool_get_response(&self, text: String) -> Result<String> {
-        Ok(text)
+
+    async fn send_notification(&self, notification: &JsonRpcNotification) -> Result<()> {
+        Self::write_line(&self.stdin, &json!(notification))
+            .await
+            .with_context(|| format!("Error writing to MCP server '{}'", self.name))
     }
 
-    fn build_tool_call_request(&self, name: &str, params: Value) -> JsonRpcRequest {
-        let tool_call = MCPToolCallParamsRequest {
-            tool_name: name.to_string(),
-            arguments: params,
-        };
-        let request = JsonRpcRequest::default(
-            "tools/get".to_string(),
-            serde_json::to_value(tool_call).ok(),
-        );
-        request
+    fn subscribe(&self) -> Option<broadcast::Receiver<Value>> {
+        Some(self.notifications.subscribe())
+    }
+
+    // close stdin so the server can exit on its own, kill it if it does not
+    async fn close(&self) -> Result<()> {
+        self.stdin.lock().await.take();
+        let child = self.child.lock().unwrap().take();
+        if let Some(mut child) = child {
+            if tokio::time::timeout(MCP_STDIO_SHUTDOWN_TIMEOUT, child.wait())
+                .await
+                .is_err()
+            {
+                child.kill().await?;
+            }
+        }
+        Ok(())
     }
+}
 
-    fn parse_tool_call_response(&self, text: String) -> Result<String> {
-        let data = serde_json::from_str(&text)?;
-        Ok(data)
+impl Drop for MCPStdioTransport {
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.lock().unwrap().take() else {
+            return;
+        };
+        if let Ok(mut stdin) = self.stdin.try_lock() {
+            stdin.take();
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if tokio::time::timeout(MCP_STDIO_SHUTDOWN_TIMEOUT, child.wait())
+                        .await
+                        .is_err()
+                    {
+                        let _ = child.kill().await;
+                    }
+                });
+            }
+            Err(_) => {
+                let _ = child.start_kill();
+            }
+        }
     }
 }
@@ -0,0 +1,353 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+use tracing::{debug, error};
+
+use crate::capabilities::{
+    rcp::{
+        JsonRpcError, JsonRpcResponse, INTERNAL_ERROR, INVALID_PARAMS, INVALID_REQUEST,
+        METHOD_NOT_FOUND, PARSE_ERROR,
+    },
+    tools::{
+        mcp::{MCPRegistry, MCP_PROTOCOL_VERSION},
+        tool::{ToolDefinition, ToolRegistry},
+    },
+};
+
+const MCP_SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+const MCP_SERVER_PATH: &str = "/mcp";
+const MCP_SESSION_ID_HEADER: &str = "Mcp-Session-Id";
+
+// MCPServer serves the tools of a ToolRegistry, and optionally the registered tools
+// of an MCPRegistry it proxies, to MCP hosts over stdio or Streamable HTTP.
+#[derive(Debug, Clone)]
+pub struct MCPServer {
+    name: String,
+    version: String,
+    tool_registry: Arc<ToolRegistry>,
+    mcp_registry: Option<Arc<MCPRegistry>>,
+    next_session: Arc<AtomicU64>,
+    sessions: Arc<Mutex<HashSet<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MCPServerToolCallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+impl MCPServer {
+    pub fn new(name: &str, tool_registry: ToolRegistry) -> Self {
+        Self {
+            name: name.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            tool_registry: Arc::new(tool_registry),
+            mcp_registry: None,
+            next_session: Arc::new(AtomicU64::new(1)),
+            sessions: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    // proxy the tools registered on the mcp registry
+    pub fn with_mcp_registry(mut self, mcp_registry: MCPRegistry) -> Self {
+        self.mcp_registry = Some(Arc::new(mcp_registry));
+        self
+    }
+
+    // Handle one JSON-RPC message.
+    // Returns the response, or None for notifications.
+    pub async fn handle_message(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+            let error = JsonRpcError::new(INVALID_REQUEST, "Invalid request");
+            return Some(json!(JsonRpcResponse::<Value>::failure(
+                id.unwrap_or(Value::Null),
+                error
+            )));
+        };
+        let Some(id) = id else {
+            debug!("Notification: {}", method);
+            return None;
+        };
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let response = match self.handle_request(method, params).await {
+            Ok(result) => JsonRpcResponse::success(id, result),
+            Err(error) => JsonRpcResponse::failure(id, error),
+        };
+        Some(json!(response))
+    }
+
+    async fn handle_request(&self, method: &str, params: Value) -> Result<Value, JsonRpcError> {
+        debug!("Request: {} {:#?}", method, params);
+        match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.tool_list() })),
+            "tools/call" => {
+                let params: MCPServerToolCallParams = serde_json::from_value(params)
+                    .map_err(|e| JsonRpcError::new(INVALID_PARAMS, &e.to_string()))?;
+                self.tool_call(params).await
+            }
+            _ => Err(JsonRpcError::new(
+                METHOD_NOT_FOUND,
+                &format!("Method not found: {}", method),
+            )),
+        }
+    }
+
+    // answer with the client version when supported, otherwise with the latest version
+    fn initialize(&self, params: &Value) -> Value {
+        let requested = params
+            .get("protocolVersion")
+            .and_then(|v| v.as_str())
+            .unwrap_or(MCP_PROTOCOL_VERSION);
+        let version = if MCP_SUPPORTED_PROTOCOL_VERSIONS.contains(&requested) {
+            requested
+        } else {
+            MCP_PROTOCOL_VERSION
+        };
+
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": { "listChanged": false } },
+            "serverInfo": { "name": self.name, "version": self.version },
+        })
+    }
+
+    fn tool_list(&self) -> Vec<Value> {
+        let mut definitions: Vec<ToolDefinition> = self
+            .tool_registry
+            .get_tools()
+            .iter()
+            .map(|t| ToolDefinition::from_tool(t.as_ref()))
+            .collect();
+        if let Some(mcp_registry) = &self.mcp_registry {
+            definitions.extend(mcp_registry.definitions.values().cloned());
+        }
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        definitions
+            .into_iter()
+            .map(|d| {
+                json!({
+                    "name": d.name,
+                    "description": d.description,
+                    "inputSchema": d.parameters,
+                })
+            })
+            .collect()
+    }
+
+    // Tool failures, native or proxied, are returned as results with isError set
+    // so the host model can see them. Unknown tools are protocol errors.
+    async fn tool_call(&self, params: MCPServerToolCallParams) -> Result<Value, JsonRpcError> {
+        if let Some(tool) = self.tool_registry.get_tool(&params.name) {
+            let result = match tool.execute(params.arguments).await {
+                Ok(value) => {
+                    let text = match &value {
+                        Value::String(s) => s.clone(),
+                        _ => value.to_string(),
+                    };
+                    let mut result = json!({
+                        "content": [{ "type": "text", "text": text }],
+                        "isError": false,
+                    });
+                    if value.is_object() {
+                        result["structuredContent"] = value;
+                    }
+                    result
+                }
+                Err(e) => tool_error(&e.to_string()),
+            };
+            return Ok(result);
+        }
+
+        if let Some(mcp_registry) = &self.mcp_registry {
+            if mcp_registry.definitions.contains_key(&params.name) {
+                let result = match mcp_registry.call_tool(&params.name, params.arguments).await {
+                    Ok(response) => serde_json::to_value(response)
+                        .map_err(|e| JsonRpcError::new(INTERNAL_ERROR, &e.to_string()))?,
+                    Err(e) => tool_error(&format!("{:#}", e)),
+                };
+                return Ok(result);
+            }
+        }
+
+        Err(JsonRpcError::new(
+            INVALID_PARAMS,
+            &format!("Unknown tool: {}", params.name),
+        ))
+    }
+
+    // Serve newline delimited JSON-RPC on stdin/stdout until stdin closes.
+    // Requests are handled concurrently, responses are written as they complete.
+    pub async fn serve_stdio(self) -> Result<()> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Value>();
+        let writer = tokio::spawn(async move {
+            let mut stdout = tokio::io::stdout();
+            while let Some(message) = receiver.recv().await {
+                let mut line = message.to_string();
+                line.push('\n');
+                if let Err(e) = stdout.write_all(line.as_bytes()).await {
+                    error!("Error writing to stdout: {}", e);
+                    break;
+                }
+                let _ = stdout.flush().await;
+            }
+        });
+
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: Value = match serde_json::from_str(&line) {
+                Ok(m) => m,
+                Err(e) => {
+                    let error = JsonRpcError::new(PARSE_ERROR, &e.to_string());
+                    let _ = sender.send(json!(JsonRpcResponse::<Value>::failure(Value::Null, error)));
+                    continue;
+                }
+            };
+            let server = self.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                if let Some(response) = server.handle_message(message).await {
+                    let _ = sender.send(response);
+                }
+            });
+        }
+
+        drop(sender);
+        let _ = writer.await;
+        Ok(())
+    }
+
+    // router returns the Streamable HTTP endpoint, to be mounted in an existing axum app
+    pub fn router(self) -> Router {
+        Router::new()
+            .route(
+                MCP_SERVER_PATH,
+                post(handle_http_post).get(handle_http_get).delete(handle_http_delete),
+            )
+            .with_state(self)
+    }
+
+    // Serve the Streamable HTTP transport on the address, the endpoint is /mcp
+    pub async fn serve_http(self, addr: SocketAddr) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Error binding MCP server to {}", addr))?;
+        debug!("MCP server listening on {}{}", addr, MCP_SERVER_PATH);
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    fn new_session_id(&self) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let count = self.next_session.fetch_add(1, Ordering::SeqCst);
+        let session_id = format!("{:x}-{:x}", nanos, count);
+        self.sessions.lock().unwrap().insert(session_id.clone());
+        session_id
+    }
+
+    // a request without a session id is accepted, an unknown or terminated one is not
+    fn check_session(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        match session_id(headers) {
+            Some(session_id) if !self.sessions.lock().unwrap().contains(session_id) => {
+                Err(StatusCode::NOT_FOUND)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn tool_error(text: &str) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": true,
+    })
+}
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(MCP_SESSION_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+}
+
+async fn handle_http_post(
+    State(server): State<MCPServer>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let message: Value = match serde_json::from_str(&body) {
+        Ok(m) => m,
+        Err(e) => {
+            let error = JsonRpcError::new(PARSE_ERROR, &e.to_string());
+            let response = json!(JsonRpcResponse::<Value>::failure(Value::Null, error));
+            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+        }
+    };
+
+    let is_initialize = message.get("method").and_then(|m| m.as_str()) == Some("initialize");
+    if !is_initialize {
+        if let Err(status) = server.check_session(&headers) {
+            return status.into_response();
+        }
+    }
+    match server.handle_message(message).await {
+        None => StatusCode::ACCEPTED.into_response(),
+        Some(response) if is_initialize => {
+            let session_id = server.new_session_id();
+            ([(MCP_SESSION_ID_HEADER, session_id)], Json(response)).into_response()
+        }
+        Some(response) => Json(response).into_response(),
+    }
+}
+
+// the server does not push messages outside of responses
+async fn handle_http_get() -> StatusCode {
+    StatusCode::METHOD_NOT_ALLOWED
+}
+
+// terminate the session, later requests with its id are answered with 404
+async fn handle_http_delete(State(server): State<MCPServer>, headers: HeaderMap) -> StatusCode {
+    let Some(session_id) = session_id(&headers) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    if server.sessions.lock().unwrap().remove(session_id) {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -2,3 +2,5 @@ pub mod request;
 pub mod response;
 pub mod tool;
 pub mod mcp;
+pub mod mcp_server;
+pub mod schema;
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 use serde_json::Value;
 
@@ -11,18 +13,42 @@ pub struct ToolCallRequest {
 
 
 #[derive(Debug, Serialize)]
-pub (super) struct MCPToolListRequest {}
+#[serde(rename_all = "camelCase")]
+pub (super) struct MCPInitializeParamsRequest {
+    pub (super) protocol_version: String,
+    pub (super) capabilities: Value,
+    pub (super) client_info: MCPImplementation,
+}
+
+#[derive(Debug, Serialize)]
+pub (super) struct MCPImplementation {
+    pub (super) name: String,
+    pub (super) version: String,
+}
 
 
+// params for the paginated list methods
 #[derive(Debug, Serialize)]
-pub (super) struct MCPToolGetParamsRequest {
-    pub (super) tool_name: String,
+pub (super) struct MCPListParamsRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub (super) cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub (super) struct MCPToolCallParamsRequest {
-    pub (super) tool_name: String,
+    pub (super) name: String,
     pub (super) arguments: Value,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub (super) meta: Option<Value>,
 }
 
+#[derive(Debug, Serialize)]
+pub (super) struct MCPResourceReadParamsRequest {
+    pub (super) uri: String,
+}
 
+#[derive(Debug, Serialize)]
+pub (super) struct MCPPromptGetParamsRequest {
+    pub (super) name: String,
+    pub (super) arguments: HashMap<String, String>,
+}
@@ -1,19 +1,47 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 
-
 #[derive(Debug, Deserialize)]
-pub (super) struct MCPToolListResponse {
-    pub (super) content: Vec<MCPToolListResponseContent>,
+#[serde(rename_all = "camelCase")]
+pub (super) struct MCPInitializeResponse {
+    pub (super) protocol_version: String,
+    #[serde(default)]
+    pub (super) capabilities: MCPServerCapabilities,
+    pub (super) server_info: Option<MCPServerInfo>,
+}
+
+// MCPServerCapabilities lists the features the server announced during initialize
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MCPServerCapabilities {
+    pub tools: Option<Value>,
+    pub resources: Option<Value>,
+    pub prompts: Option<Value>,
+    pub logging: Option<Value>,
 }
+
 #[derive(Debug, Deserialize, Clone)]
-pub (super) struct MCPToolListResponseContent {
-    #[allow(dead_code)]
-    r#type: String,
-    pub (super) text: String,
+pub struct MCPServerInfo {
+    pub name: String,
+    pub version: Option<String>,
 }
 
+
+// MCPTool is a tool as described by tools/list
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPTool {
+    pub name: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub input_schema: Value,
+    pub output_schema: Option<Value>,
+}
+
+
+// Adapter definitions, parsed from the text returned by non standard servers
 #[derive(Debug, Deserialize)]
 pub (super) struct MCPToolListDefinition {
     pub (super) name: String,
@@ -30,16 +58,183 @@ pub (super) struct MCPToolGetDefinition {
 }
 
 
+// MCPToolCallResponse is the result of tools/call
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPToolCallResponse {
+    #[serde(default)]
+    pub content: Vec<MCPContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl MCPToolCallResponse {
+
+    // text returns the first text block
+    pub fn text(&self) -> Option<String> {
+        self.content.iter().find_map(|c| match c {
+            MCPContent::Text { text } => Some(text.clone()),
+            _ => None,
+        })
+    }
+
+    // into_value converts the result into the value sent back to the llm.
+    // Structured content wins, a single text block is parsed as json when possible,
+    // anything else is returned as the list of content blocks.
+    pub fn into_value(self) -> Value {
+        if let Some(value) = self.structured_content {
+            return value;
+        }
+        if let [MCPContent::Text { text }] = self.content.as_slice() {
+            return serde_json::from_str(text).unwrap_or(Value::String(text.clone()));
+        }
+        serde_json::to_value(self.content).unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum MCPContent {
+    #[serde(rename = "text")]
+    Text { text: String },
+
+    #[serde(rename = "image")]
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+
+    #[serde(rename = "audio")]
+    Audio {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+
+    #[serde(rename = "resource")]
+    Resource { resource: MCPResourceContents },
+
+    #[serde(rename = "resource_link")]
+    ResourceLink {
+        uri: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+// MCPProgress is sent by the server in notifications/progress while a request is running
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPProgress {
+    pub progress_token: Value,
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+// MCPResourceContents holds either text or base64 blob contents of a resource
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPResourceContents {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+impl MCPResourceContents {
+
+    // to_text returns the text contents, binary contents are described instead
+    pub fn to_text(&self) -> String {
+        match (&self.text, &self.mime_type) {
+            (Some(text), _) => text.clone(),
+            (None, Some(mime_type)) => format!("[binary resource {} ({})]", self.uri, mime_type),
+            (None, None) => format!("[binary resource {}]", self.uri),
+        }
+    }
+}
+
+impl MCPContent {
+
+    // to_text returns the text of the block, other content is described instead
+    pub fn to_text(&self) -> String {
+        match self {
+            MCPContent::Text { text } => text.clone(),
+            MCPContent::Image { mime_type, .. } => format!("[image ({})]", mime_type),
+            MCPContent::Audio { mime_type, .. } => format!("[audio ({})]", mime_type),
+            MCPContent::Resource { resource } => resource.to_text(),
+            MCPContent::ResourceLink { uri, name, .. } => format!("[resource {} ({})]", name, uri),
+        }
+    }
+}
+
+
+// MCPResource is a resource as described by resources/list
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPResource {
+    pub uri: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+}
+
+// MCPResourceTemplate is a parameterized resource as described by resources/templates/list
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPResourceTemplate {
+    pub uri_template: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+}
 
 #[derive(Debug, Deserialize)]
-pub (super) struct MCPToolCallResponse {
-    pub (super) content: Vec<MCPToolCallResponseContent>,
+pub (super) struct MCPResourceReadResponse {
+    pub (super) contents: Vec<MCPResourceContents>,
 }
 
+
+// MCPPrompt is a prompt template as described by prompts/list
 #[derive(Debug, Deserialize, Clone)]
-pub (super) struct MCPToolCallResponseContent {
-    #[allow(dead_code)]
-    r#type: String,
-    pub (super) text: String,
+#[serde(rename_all = "camelCase")]
+pub struct MCPPrompt {
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<MCPPromptArgument>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MCPPromptArgument {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+// MCPPromptGetResponse is the result of prompts/get
+#[derive(Debug, Deserialize, Clone)]
+pub struct MCPPromptGetResponse {
+    pub description: Option<String>,
+    pub messages: Vec<MCPPromptMessage>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct MCPPromptMessage {
+    pub role: String,
+    pub content: MCPContent,
+}
@@ -0,0 +1,687 @@
+use schemars::{generate::SchemaSettings, JsonSchema};
+use serde::Serialize;
+use serde_json::{json, Map, Number, Value};
+use std::fmt;
+
+// string formats every provider accepts, schemars also emits formats like uint32 or double
+const PORTABLE_FORMATS: [&str; 6] = ["date-time", "date", "time", "email", "uri", "uuid"];
+
+// Validation of tool arguments against the json schema of the tool,
+// and generation of portable schemas from rust types.
+//
+// Covers the subset of json schema the providers accept for tool parameters:
+// type, enum, const, properties, required, additionalProperties, items, the length,
+// size and range keywords, allOf/anyOf/oneOf and local $ref to $defs or definitions.
+// Other keywords, like pattern and format, are not checked.
+
+// ValidationError is one violation, path points to the value like $.symbols[0]
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// ArgumentsError is returned when the arguments of a tool call do not match the schema
+#[derive(Debug, Clone)]
+pub struct ArgumentsError {
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for ArgumentsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "Invalid arguments: {}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ArgumentsError {}
+
+// validate returns the violations of the value, empty when it matches the schema
+pub fn validate(schema: &Value, value: &Value) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    validate_at(schema, schema, value, "$", &mut errors);
+    errors
+}
+
+// Coerce fixes the common mistakes of models before validation:
+// numbers and booleans sent as strings, numbers sent for strings, a single item
+// sent for an array and arrays or objects sent as json strings.
+pub fn coerce(schema: &Value, value: &mut Value) {
+    coerce_at(schema, schema, value);
+}
+
+// schema_for generates the json schema of the type for tool parameters or structured output.
+// Doc comments become descriptions, the schema is made portable across providers.
+pub fn schema_for<T: JsonSchema + ?Sized>() -> Value {
+    let generator = SchemaSettings::draft2020_12()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .for_deserialize()
+        .into_generator();
+    portable(&generator.into_root_schema_for::<T>().to_value())
+}
+
+// Portable rewrites the schema to the subset Anthropic, OpenAI and Gemini all accept:
+// local $ref are inlined, $schema, $id, $defs, definitions, titles and uncommon formats
+// are dropped, and objects always list their properties.
+// Recursive references can not be inlined, they accept any value.
+pub fn portable(schema: &Value) -> Value {
+    portable_at(schema, schema, &mut Vec::new())
+}
+
+// Strict rewrites a portable schema for the OpenAI strict mode: every object lists all
+// its properties as required and allows no others, optional properties become nullable,
+// oneOf becomes anyOf and defaults are dropped.
+pub fn strict(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => {
+            let mut rewritten = Map::new();
+            for (key, value) in map {
+                match key.as_str() {
+                    "default" => {}
+                    "enum" | "const" | "examples" => {
+                        rewritten.insert(key.clone(), value.clone());
+                    }
+                    "oneOf" => {
+                        rewritten.insert("anyOf".to_string(), strict_values(value));
+                    }
+                    "properties" => {
+                        let required: Vec<&str> = map
+                            .get("required")
+                            .and_then(|r| r.as_array())
+                            .map(|r| r.iter().filter_map(|n| n.as_str()).collect())
+                            .unwrap_or_default();
+                        let properties = value
+                            .as_object()
+                            .map(|properties| {
+                                properties
+                                    .iter()
+                                    .map(|(name, property)| {
+                                        let property = match required.contains(&name.as_str()) {
+                                            true => strict(property),
+                                            false => nullable(strict(property)),
+                                        };
+                                        (name.clone(), property)
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        rewritten.insert(key.clone(), Value::Object(properties));
+                    }
+                    "required" => {}
+                    _ => {
+                        rewritten.insert(key.clone(), strict_values(value));
+                    }
+                }
+            }
+            if let Some(Value::Object(properties)) = rewritten.get("properties") {
+                let names = properties.keys().map(|n| Value::String(n.clone())).collect();
+                rewritten.insert("required".to_string(), Value::Array(names));
+                rewritten.insert("additionalProperties".to_string(), Value::Bool(false));
+            }
+            Value::Object(rewritten)
+        }
+        _ => schema.clone(),
+    }
+}
+
+fn strict_values(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(strict).collect()),
+        _ => strict(value),
+    }
+}
+
+// nullable adds null to the types of the schema
+fn nullable(mut schema: Value) -> Value {
+    match schema.get("type").cloned() {
+        Some(Value::String(name)) if name != "null" => {
+            schema["type"] = json!([name, "null"]);
+            if let Some(Value::Array(allowed)) = schema.get_mut("enum") {
+                allowed.push(Value::Null);
+            }
+            schema
+        }
+        Some(Value::Array(mut names)) => {
+            if !names.contains(&json!("null")) {
+                names.push(json!("null"));
+                schema["type"] = Value::Array(names);
+            }
+            schema
+        }
+        _ => json!({ "anyOf": [schema, { "type": "null" }] }),
+    }
+}
+
+fn portable_at(root: &Value, schema: &Value, refs: &mut Vec<String>) -> Value {
+    match schema {
+        Value::Object(map) => {
+            if let Some(reference) = map.get("$ref").and_then(|r| r.as_str()) {
+                if refs.iter().any(|r| r == reference) {
+                    return Value::Object(Map::new());
+                }
+                let Some(target) = reference.strip_prefix('#').and_then(|p| root.pointer(p)) else {
+                    return Value::Object(Map::new());
+                };
+                refs.push(reference.to_string());
+                let mut inlined = portable_at(root, target, refs);
+                refs.pop();
+                // keywords next to the $ref, like a description, win over the target
+                if let Value::Object(inlined_map) = &mut inlined {
+                    for (key, value) in map.iter().filter(|(key, _)| key.as_str() != "$ref") {
+                        inlined_map.insert(key.clone(), portable_at(root, value, refs));
+                    }
+                }
+                return inlined;
+            }
+
+            let mut portable = Map::new();
+            for (key, value) in map {
+                match key.as_str() {
+                    "$schema" | "$id" | "$defs" | "definitions" | "title" => {}
+                    "format" if !value.as_str().is_some_and(|f| PORTABLE_FORMATS.contains(&f)) => {}
+                    // values, not schemas
+                    "enum" | "const" | "default" | "examples" => {
+                        portable.insert(key.clone(), value.clone());
+                    }
+                    // property names are not schemas, keep them as they are
+                    "properties" => {
+                        let properties = value
+                            .as_object()
+                            .map(|properties| {
+                                properties
+                                    .iter()
+                                    .map(|(name, property)| (name.clone(), portable_at(root, property, refs)))
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        portable.insert(key.clone(), Value::Object(properties));
+                    }
+                    _ => {
+                        portable.insert(key.clone(), portable_at(root, value, refs));
+                    }
+                }
+            }
+            if portable.get("type").and_then(|t| t.as_str()) == Some("object")
+                && !portable.contains_key("properties")
+                && !portable.contains_key("additionalProperties")
+            {
+                portable.insert("properties".to_string(), Value::Object(Map::new()));
+            }
+            Value::Object(portable)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|i| portable_at(root, i, refs)).collect()),
+        _ => schema.clone(),
+    }
+}
+
+fn validate_at(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => return fail(errors, path, "no value is allowed here".to_string()),
+        Value::Object(_) => resolve(root, schema),
+        _ => return,
+    };
+    let Some(schema) = schema else {
+        return fail(errors, path, "the schema has an unresolved $ref".to_string());
+    };
+
+    if let Some(types) = schema.get("type") {
+        let types = type_names(types);
+        if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+            return fail(errors, path, format!("expected {}, got {}", types.join(" or "), type_name(value)));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            fail(errors, path, format!("expected one of {}, got {}", allowed.join(", "), value));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            fail(errors, path, format!("expected {}, got {}", expected, value));
+        }
+    }
+
+    match value {
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                if length < min {
+                    fail(errors, path, format!("expected at least {} characters, got {}", min, length));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                if length > max {
+                    fail(errors, path, format!("expected at most {} characters, got {}", max, length));
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            let bound = |name: &str| schema.get(name).and_then(|v| v.as_f64());
+            if let Some(min) = bound("minimum").filter(|min| number < *min) {
+                fail(errors, path, format!("expected a value >= {}, got {}", min, number));
+            }
+            if let Some(max) = bound("maximum").filter(|max| number > *max) {
+                fail(errors, path, format!("expected a value <= {}, got {}", max, number));
+            }
+            if let Some(min) = bound("exclusiveMinimum").filter(|min| number <= *min) {
+                fail(errors, path, format!("expected a value > {}, got {}", min, number));
+            }
+            if let Some(max) = bound("exclusiveMaximum").filter(|max| number >= *max) {
+                fail(errors, path, format!("expected a value < {}, got {}", max, number));
+            }
+        }
+        Value::Array(items) => {
+            let length = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                if length < min {
+                    fail(errors, path, format!("expected at least {} items, got {}", min, length));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                if length > max {
+                    fail(errors, path, format!("expected at most {} items, got {}", max, length));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(root, item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+                for name in required.iter().filter_map(|n| n.as_str()) {
+                    if !map.contains_key(name) {
+                        fail(errors, path, format!("missing required property '{}'", name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property) in map {
+                let property_path = format!("{}.{}", path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    Some(property_schema) => {
+                        validate_at(root, property_schema, property, &property_path, errors)
+                    }
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            fail(errors, path, format!("unexpected property '{}'", name))
+                        }
+                        Some(additional) => {
+                            validate_at(root, additional, property, &property_path, errors)
+                        }
+                        None => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(|a| a.as_array()) {
+        for sub_schema in all {
+            validate_at(root, sub_schema, value, path, errors);
+        }
+    }
+    for (keyword, exactly_one) in [("anyOf", false), ("oneOf", true)] {
+        if let Some(options) = schema.get(keyword).and_then(|a| a.as_array()) {
+            let matches = options
+                .iter()
+                .filter(|option| {
+                    let mut option_errors = Vec::new();
+                    validate_at(root, option, value, path, &mut option_errors);
+                    option_errors.is_empty()
+                })
+                .count();
+            if matches == 0 || (exactly_one && matches > 1) {
+                let message = match exactly_one {
+                    true => "expected a value matching exactly one of the oneOf schemas",
+                    false => "expected a value matching one of the anyOf schemas",
+                };
+                fail(errors, path, message.to_string());
+            }
+        }
+    }
+}
+
+fn fail(errors: &mut Vec<ValidationError>, path: &str, message: String) {
+    errors.push(ValidationError {
+        path: path.to_string(),
+        message,
+    });
+}
+
+fn coerce_at(root: &Value, schema: &Value, value: &mut Value) {
+    let Some(schema) = resolve(root, schema) else {
+        return;
+    };
+    let types = schema.get("type").map(type_names).unwrap_or_default();
+
+    if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+        if let Some(coerced) = types.iter().find_map(|t| coerce_value(value, t)) {
+            *value = coerced;
+        }
+    }
+
+    match value {
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                items
+                    .iter_mut()
+                    .for_each(|item| coerce_at(root, item_schema, item));
+            }
+        }
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property) in map.iter_mut() {
+                if let Some(property_schema) = properties.and_then(|p| p.get(name)) {
+                    coerce_at(root, property_schema, property);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// coerce_value converts the value to the type, None when there is no sensible conversion
+fn coerce_value(value: &Value, r#type: &str) -> Option<Value> {
+    match (r#type, value) {
+        ("integer", Value::String(text)) => {
+            let text = text.trim();
+            text.parse::<i64>()
+                .map(Value::from)
+                .ok()
+                .or_else(|| {
+                    let number = text.parse::<f64>().ok()?;
+                    (number.fract() == 0.0 && number.abs() < i64::MAX as f64)
+                        .then(|| Value::from(number as i64))
+                })
+        }
+        ("integer", Value::Number(number)) => {
+            let number = number.as_f64()?;
+            (number.fract() == 0.0).then(|| Value::from(number as i64))
+        }
+        ("number", Value::String(text)) => {
+            let number = text.trim().parse::<f64>().ok()?;
+            Number::from_f64(number).map(Value::Number)
+        }
+        ("boolean", Value::String(text)) => match text.trim().to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("string", Value::Number(number)) => Some(Value::String(number.to_string())),
+        ("string", Value::Bool(flag)) => Some(Value::String(flag.to_string())),
+        ("array", Value::String(text)) => match serde_json::from_str(text) {
+            Ok(Value::Array(items)) => Some(Value::Array(items)),
+            _ => Some(Value::Array(vec![value.clone()])),
+        },
+        ("array", Value::Null) => None,
+        ("array", _) => Some(Value::Array(vec![value.clone()])),
+        ("object", Value::String(text)) => match serde_json::from_str(text) {
+            Ok(Value::Object(map)) => Some(Value::Object(map)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// resolve follows local $ref like #/$defs/Ticker
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> Option<&'a Value> {
+    let mut schema = schema;
+    // a bounded number of hops guards against cyclic references
+    for _ in 0..32 {
+        match schema.get("$ref").and_then(|r| r.as_str()) {
+            Some(reference) => schema = root.pointer(reference.strip_prefix('#')?)?,
+            None => return Some(schema),
+        }
+    }
+    None
+}
+
+fn type_names(types: &Value) -> Vec<&str> {
+    match types {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn is_type(value: &Value, r#type: &str) -> bool {
+    match r#type {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(number) => {
+                number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            _ => false,
+        },
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn paths(errors: &[ValidationError]) -> Vec<&str> {
+        errors.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    fn sorted(names: &Value) -> Vec<&str> {
+        let mut names: Vec<&str> = names
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|n| n.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn type_and_required_failures() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "symbol": {"type": "string"},
+                "count": {"type": "integer"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+            },
+            "required": ["symbol", "count"],
+        });
+        let errors = validate(&schema, &json!({"count": "x", "tags": ["a", 1]}));
+        assert_eq!(paths(&errors), ["$", "$.count", "$.tags[1]"]);
+        assert_eq!(errors[0].message, "missing required property 'symbol'");
+        assert_eq!(errors[1].message, "expected integer, got string");
+        assert_eq!(
+            errors[2].to_string(),
+            "$.tags[1]: expected string, got integer"
+        );
+
+        assert!(validate(&schema, &json!({"symbol": "AAPL", "count": 2})).is_empty());
+        assert_eq!(paths(&validate(&schema, &json!([]))), ["$"]);
+    }
+
+    #[test]
+    fn coercion() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "count": {"type": "integer"},
+                "price": {"type": "number"},
+                "flag": {"type": "boolean"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "name": {"type": "string"},
+            },
+        });
+        let mut value =
+            json!({"count": "3", "price": "1.5", "flag": "true", "tags": "a", "name": "n"});
+        coerce(&schema, &mut value);
+        assert_eq!(
+            value,
+            json!({"count": 3, "price": 1.5, "flag": true, "tags": ["a"], "name": "n"})
+        );
+        assert!(validate(&schema, &value).is_empty());
+
+        // values without a sensible conversion are left for the validation to report
+        let mut value = json!({"count": "three", "flag": "yes"});
+        coerce(&schema, &mut value);
+        assert_eq!(value, json!({"count": "three", "flag": "yes"}));
+        assert_eq!(paths(&validate(&schema, &value)), ["$.count", "$.flag"]);
+    }
+
+    #[test]
+    fn refs_resolved() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "home": {"$ref": "#/$defs/Address", "description": "Home address"},
+                "work": {"$ref": "#/$defs/Address"},
+            },
+            "$defs": {
+                "Address": {
+                    "title": "Address",
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"],
+                },
+            },
+        });
+        let errors = validate(&schema, &json!({"home": {"city": 1}, "work": {}}));
+        assert_eq!(paths(&errors), ["$.home.city", "$.work"]);
+
+        // coercion follows the references
+        let mut value = json!({"home": {"city": 7}});
+        coerce(&schema, &mut value);
+        assert_eq!(value, json!({"home": {"city": "7"}}));
+
+        // portable inlines the references and keeps the keywords next to them
+        let inlined = portable(&schema);
+        assert!(inlined.get("$defs").is_none());
+        assert_eq!(
+            inlined["properties"]["home"],
+            json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}},
+                "required": ["city"],
+                "description": "Home address",
+            })
+        );
+        assert_eq!(inlined["properties"]["work"]["required"], json!(["city"]));
+
+        // a recursive reference accepts any value
+        let recursive = json!({
+            "type": "object",
+            "properties": {"tree": {"$ref": "#/$defs/Node"}},
+            "$defs": {
+                "Node": {"type": "object", "properties": {"child": {"$ref": "#/$defs/Node"}}},
+            },
+        });
+        assert_eq!(
+            portable(&recursive)["properties"]["tree"],
+            json!({"type": "object", "properties": {"child": {}}})
+        );
+    }
+
+    /// An order
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct Order {
+        /// Order id
+        id: String,
+        note: Option<String>,
+        #[serde(default)]
+        quantity: u32,
+        shipping: Shipping,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct Shipping {
+        city: String,
+        floor: Option<u32>,
+    }
+
+    #[test]
+    fn portable_and_strict_nested_objects() {
+        let schema = schema_for::<Order>();
+        assert!(schema.get("title").is_none());
+        assert!(schema.get("$schema").is_none());
+        assert_eq!(schema["description"], "An order");
+        assert_eq!(sorted(&schema["required"]), ["id", "shipping"]);
+        // the nested type is inlined
+        assert_eq!(
+            sorted(&schema["properties"]["shipping"]["required"]),
+            ["city"]
+        );
+
+        let strict = strict(&schema);
+        assert_eq!(
+            sorted(&strict["required"]),
+            ["id", "note", "quantity", "shipping"]
+        );
+        assert_eq!(strict["additionalProperties"], json!(false));
+        assert_eq!(strict["properties"]["id"]["type"], "string");
+        assert_eq!(
+            sorted(&strict["properties"]["note"]["type"]),
+            ["null", "string"]
+        );
+        assert_eq!(
+            sorted(&strict["properties"]["quantity"]["type"]),
+            ["integer", "null"]
+        );
+        assert!(strict["properties"]["quantity"].get("default").is_none());
+
+        let shipping = &strict["properties"]["shipping"];
+        assert_eq!(sorted(&shipping["required"]), ["city", "floor"]);
+        assert_eq!(shipping["additionalProperties"], json!(false));
+        assert_eq!(shipping["properties"]["city"]["type"], "string");
+        assert_eq!(
+            sorted(&shipping["properties"]["floor"]["type"]),
+            ["integer", "null"]
+        );
+
+        // the strict schema accepts nulls for the optional properties
+        let value = json!({"id": "1", "note": null, "quantity": null, "shipping": {"city": "Paris", "floor": null}});
+        assert!(validate(&strict, &value).is_empty());
+        assert_eq!(
+            paths(&validate(
+                &strict,
+                &json!({"id": "1", "note": null, "quantity": 1, "shipping": {"city": "Paris"}})
+            )),
+            ["$.shipping"]
+        );
+    }
+}
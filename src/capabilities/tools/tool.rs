@@ -33,6 +33,16 @@ impl ToolRegistry {
         values.cloned().collect()
     }
 
+    // register a tool shared with another registry
+    pub fn register_shared_tool(&mut self, tool: Arc<dyn Tool>) {
+        self.registry.insert(tool.name(), tool);
+    }
+
+    // keep the tools whose name matches the predicate
+    pub fn retain_tools<F: Fn(&str) -> bool>(&mut self, f: F) {
+        self.registry.retain(|name, _| f(name));
+    }
+
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -0,0 +1,302 @@
+use reqwest::{header::HeaderMap, StatusCode};
+use serde_json::Value;
+use std::{fmt, time::Duration};
+
+use crate::{capabilities::completion::response::CompletionResponse, http::retry::server_delay};
+
+// Error is the kind of a failure talking to a provider or an MCP server, or running a tool.
+// The public api returns anyhow errors, branch on the kind with
+// `error.downcast_ref::<agentic_core::Error>()`.
+#[derive(Debug, Clone)]
+pub enum Error {
+    // 429 or quota errors, retry_after is the wait the provider asked for
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    // invalid or missing credentials, or no permission for the resource
+    Authentication { message: String },
+    // the provider rejected the request
+    InvalidRequest { status: u16, provider_message: String },
+    // the prompt does not fit in the context window of the model
+    ContextLengthExceeded { message: String },
+    // refused or blocked by a safety filter
+    ContentFiltered { message: String },
+    // 5xx responses, overloaded providers and errors reported inside a stream
+    Provider { status: Option<u16>, message: String },
+    Timeout { message: String },
+    // connection failures and broken streams
+    Transport { message: String },
+    // a body that could not be parsed
+    Decode { message: String },
+    ToolExecution { tool: String, message: String },
+    // output cut off by max_tokens, the response holds the partial output
+    Truncated { response: Box<CompletionResponse> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RateLimited {
+                retry_after: Some(retry_after),
+                message,
+            } => write!(f, "Rate limited, retry after {:?}: {}", retry_after, message),
+            Error::RateLimited { message, .. } => write!(f, "Rate limited: {}", message),
+            Error::Authentication { message } => write!(f, "Authentication failed: {}", message),
+            Error::InvalidRequest {
+                status,
+                provider_message,
+            } => write!(f, "Invalid request ({}): {}", status, provider_message),
+            Error::ContextLengthExceeded { message } => {
+                write!(f, "Context length exceeded: {}", message)
+            }
+            Error::ContentFiltered { message } => write!(f, "Content filtered: {}", message),
+            Error::Provider {
+                status: Some(status),
+                message,
+            } => write!(f, "Provider error ({}): {}", status, message),
+            Error::Provider { message, .. } => write!(f, "Provider error: {}", message),
+            Error::Timeout { message } => write!(f, "Timeout: {}", message),
+            Error::Transport { message } => write!(f, "Transport error: {}", message),
+            Error::Decode { message } => write!(f, "Decode error: {}", message),
+            Error::ToolExecution { tool, message } => {
+                write!(f, "Tool {} failed: {}", tool, message)
+            }
+            Error::Truncated { response } => write!(
+                f,
+                "Completion truncated at max_tokens after {} output tokens",
+                response.usage.output_tokens
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        let message = error.to_string();
+        if error.is_timeout() {
+            Error::Timeout { message }
+        } else if error.is_decode() {
+            Error::Decode { message }
+        } else {
+            Error::Transport { message }
+        }
+    }
+}
+
+impl Error {
+    // from_response reads the body of a failed response and parses the provider error
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        match response.text().await {
+            Ok(body) => Self::from_http(status, &headers, &body),
+            Err(e) => e.into(),
+        }
+    }
+
+    // from_http parses the error bodies of the providers:
+    // anthropic {"error": {"type", "message"}}, openai {"error": {"type", "code", "message"}}
+    // and gemini {"error": {"status", "message"}}
+    pub(crate) fn from_http(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let json: Option<Value> = serde_json::from_str(body).ok();
+        let error = json.as_ref().map(|j| j.get("error").unwrap_or(j));
+        let field = |name: &str| {
+            error
+                .and_then(|e| e.get(name))
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_lowercase())
+                .unwrap_or_default()
+        };
+        let kinds = [field("type"), field("code"), field("status")];
+        let is_kind = |patterns: &[&str]| {
+            kinds
+                .iter()
+                .any(|k| !k.is_empty() && patterns.iter().any(|p| k.contains(p)))
+        };
+
+        let message = error
+            .and_then(|e| e.get("message"))
+            .and_then(|m| m.as_str())
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| body.to_string());
+        let lower = message.to_lowercase();
+
+        if status == StatusCode::TOO_MANY_REQUESTS
+            || is_kind(&["rate_limit", "resource_exhausted", "insufficient_quota"])
+        {
+            return Error::RateLimited {
+                retry_after: server_delay(headers),
+                message,
+            };
+        }
+        if status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::FORBIDDEN
+            || is_kind(&["authentication", "permission", "unauthenticated", "invalid_api_key"])
+        {
+            return Error::Authentication { message };
+        }
+        if is_kind(&["context_length_exceeded"])
+            || [
+                "prompt is too long",
+                "context length",
+                "context window",
+                "maximum context",
+                "input token count",
+            ]
+            .iter()
+            .any(|p| lower.contains(p))
+        {
+            return Error::ContextLengthExceeded { message };
+        }
+        if is_kind(&["content_filter", "content_policy", "safety"]) {
+            return Error::ContentFiltered { message };
+        }
+        if status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::GATEWAY_TIMEOUT
+            || is_kind(&["deadline_exceeded"])
+        {
+            return Error::Timeout { message };
+        }
+        if status.is_client_error() {
+            return Error::InvalidRequest {
+                status: status.as_u16(),
+                provider_message: message,
+            };
+        }
+        Error::Provider {
+            status: Some(status.as_u16()),
+            message,
+        }
+    }
+
+    // retry_after is the wait the provider asked for, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(status: u16, headers: &[(&'static str, &'static str)], body: &str) -> Error {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, value.parse().unwrap());
+        }
+        Error::from_http(StatusCode::from_u16(status).unwrap(), &map, body)
+    }
+
+    #[test]
+    fn anthropic_errors() {
+        let body = r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of requests has exceeded your rate limit"}}"#;
+        let rate_limited = error(429, &[("retry-after", "30")], body);
+        assert!(
+            matches!(&rate_limited, Error::RateLimited { message, .. } if message.starts_with("Number of requests"))
+        );
+        assert_eq!(rate_limited.retry_after(), Some(Duration::from_secs(30)));
+
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(matches!(
+            error(529, &[], body),
+            Error::Provider {
+                status: Some(529),
+                ..
+            }
+        ));
+
+        let body = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#;
+        assert!(matches!(
+            error(400, &[], body),
+            Error::ContextLengthExceeded { .. }
+        ));
+
+        let body = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+        assert!(matches!(
+            error(401, &[], body),
+            Error::Authentication { .. }
+        ));
+    }
+
+    #[test]
+    fn openai_errors() {
+        let body = r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#;
+        assert!(matches!(
+            error(400, &[], body),
+            Error::ContextLengthExceeded { .. }
+        ));
+
+        let body = r#"{"error":{"message":"Invalid value for 'temperature'","type":"invalid_request_error","param":"temperature","code":null}}"#;
+        assert!(matches!(
+            error(400, &[], body),
+            Error::InvalidRequest { status: 400, provider_message } if provider_message == "Invalid value for 'temperature'"
+        ));
+
+        // quota errors are rate limits whatever the status
+        let body = r#"{"error":{"message":"You exceeded your current quota","type":"insufficient_quota","code":"insufficient_quota"}}"#;
+        let quota = error(400, &[("retry-after-ms", "1500")], body);
+        assert!(matches!(quota, Error::RateLimited { .. }));
+        assert_eq!(quota.retry_after(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn gemini_errors() {
+        let body = r#"{"error":{"code":429,"message":"Resource has been exhausted (e.g. check quota).","status":"RESOURCE_EXHAUSTED"}}"#;
+        let rate_limited = error(429, &[("retry-after", "7")], body);
+        assert!(matches!(rate_limited, Error::RateLimited { .. }));
+        assert_eq!(rate_limited.retry_after(), Some(Duration::from_secs(7)));
+
+        let body = r#"{"error":{"code":400,"message":"The input token count (1200000) exceeds the maximum number of tokens allowed (1048576).","status":"INVALID_ARGUMENT"}}"#;
+        assert!(matches!(
+            error(400, &[], body),
+            Error::ContextLengthExceeded { .. }
+        ));
+
+        let body = r#"{"error":{"code":504,"message":"Deadline expired before operation could complete.","status":"DEADLINE_EXCEEDED"}}"#;
+        assert!(matches!(error(504, &[], body), Error::Timeout { .. }));
+
+        let body =
+            r#"{"error":{"code":503,"message":"The model is overloaded.","status":"UNAVAILABLE"}}"#;
+        assert!(matches!(
+            error(503, &[], body),
+            Error::Provider {
+                status: Some(503),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn body_without_provider_error() {
+        let gateway = error(502, &[], "<html>Bad Gateway</html>");
+        assert!(matches!(
+            &gateway,
+            Error::Provider { status: Some(502), message } if message == "<html>Bad Gateway</html>"
+        ));
+        assert_eq!(gateway.retry_after(), None);
+        assert!(matches!(
+            error(404, &[], "Not Found"),
+            Error::InvalidRequest { status: 404, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn from_response_reads_the_body() {
+        let response = axum::http::Response::builder()
+            .status(429)
+            .header("retry-after", "2")
+            .body(r#"{"error":{"code":429,"message":"Quota exceeded","status":"RESOURCE_EXHAUSTED"}}"#)
+            .unwrap();
+        let error = Error::from_response(reqwest::Response::from(response)).await;
+        assert!(
+            matches!(&error, Error::RateLimited { message, .. } if message == "Quota exceeded")
+        );
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(2)));
+    }
+}
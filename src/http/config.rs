@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT},
+    Client, Proxy,
+};
+use std::time::Duration;
+
+use crate::http::retry::RetryPolicy;
+
+const CLIENT_USER_AGENT: &str = concat!("agentic-core/", env!("CARGO_PKG_VERSION"));
+
+// ClientConfig is the http configuration shared by the provider, embedding and MCP clients.
+// An injected reqwest client is used as is, the timeouts and the proxy only apply to
+// the client built from the config. Headers and the user agent are sent on every request.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    // replaces the provider base url, for gateways, compatible endpoints or mock servers
+    pub base_url: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    // timeout between reads of the response body
+    pub read_timeout: Option<Duration>,
+    // timeout of the whole request, keep it unset or long for streams
+    pub timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    pub headers: HeaderMap,
+    pub user_agent: String,
+    pub client: Option<Client>,
+    pub retry_policy: RetryPolicy,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            connect_timeout: None,
+            read_timeout: None,
+            timeout: None,
+            proxy: None,
+            headers: HeaderMap::new(),
+            user_agent: CLIENT_USER_AGENT.to_string(),
+            client: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    // add a header sent on every request
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name {}", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header {}", name))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    // use the client instead of building one, e.g. to share a connection pool
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    // base_url returns the configured base url or the provider default
+    pub fn base_url_or(&self, default: &str) -> String {
+        self.base_url.clone().unwrap_or_else(|| default.to_string())
+    }
+
+    pub(crate) fn build_client(&self) -> Result<Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).with_context(|| format!("Invalid proxy {}", proxy))?;
+            builder = builder.proxy(proxy);
+        }
+        builder.build().context("Error building http client")
+    }
+
+    // default_headers returns the configured headers with the user agent
+    pub(crate) fn default_headers(&self) -> Result<HeaderMap> {
+        let mut headers = self.headers.clone();
+        if !headers.contains_key(USER_AGENT) {
+            let user_agent = HeaderValue::from_str(&self.user_agent)
+                .with_context(|| format!("Invalid user agent {}", self.user_agent))?;
+            headers.insert(USER_AGENT, user_agent);
+        }
+        Ok(headers)
+    }
+}
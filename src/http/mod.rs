@@ -1,63 +1,185 @@
+pub mod config;
+pub mod retry;
+
 use anyhow::Result;
-use reqwest::Client;
-use tracing::debug;
+use reqwest::{header::HeaderMap, Client, RequestBuilder, Response};
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    http::{config::ClientConfig, retry::RetryPolicy},
+};
 
 #[derive(Debug, Clone)]
 pub struct HttpClient {
     client: Client,
+    retry_policy: RetryPolicy,
+    // sent on every request, request headers override them
+    headers: HeaderMap,
 }
 
 impl HttpClient {
 
     //new creates a new httpclient with the url
     pub fn new() -> Result<Self> {
+        Self::from_config(&ClientConfig::default())
+    }
+
+    pub fn from_config(config: &ClientConfig) -> Result<Self> {
         Ok(Self {
-            client: Client::new(),
+            client: config.build_client()?,
+            retry_policy: config.retry_policy.clone(),
+            headers: config.default_headers()?,
         })
     }
 
-    //send an https post
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    // send sends the request and retries it following the retry policy.
+    // Once the attempts are used up the last response is returned as is.
+    pub async fn send(&self, request: RequestBuilder, idempotent: bool) -> reqwest::Result<Response> {
+        let policy = &self.retry_policy;
+        let mut attempt = 1;
+        loop {
+            // json bodies can always be cloned, a streamed body is sent once
+            let Some(next) = request.try_clone() else {
+                return request.send().await;
+            };
+
+            let delay = match next.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    if status.is_success()
+                        || attempt >= policy.max_attempts
+                        || !policy.should_retry_status(status, idempotent)
+                    {
+                        return Ok(response);
+                    }
+                    match policy.delay(attempt, Some(response.headers())) {
+                        Some(delay) => {
+                            warn!("Attempt {} got {}, retrying in {:?}", attempt, status, delay);
+                            delay
+                        }
+                        None => return Ok(response),
+                    }
+                }
+                Err(e) => {
+                    if attempt >= policy.max_attempts || !policy.should_retry_error(&e, idempotent) {
+                        return Err(e);
+                    }
+                    let delay = policy.backoff(attempt);
+                    warn!("Attempt {} failed: {}, retrying in {:?}", attempt, e, delay);
+                    delay
+                }
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    //send an https post.
+    //Requests with side effects, like completions, pass idempotent false so they are only
+    //retried when the server rejected them.
     pub async fn post_request<T: serde::de::DeserializeOwned + Send>(
         &self,
         url: String,
         headers: Option<reqwest::header::HeaderMap>,
         body: serde_json::Value,
+        idempotent: bool,
     ) -> Result<T> {
         debug!("Url: {}", url);
-        let mut request = self.client.post(url);
+        let mut request = self.client.post(url).headers(self.headers.clone());
 
         if let Some(h) = headers {
             request = request.headers(h);
         }
 
-        let response = request.json(&body).send().await?;
+        let response = self
+            .send(request.json(&body), idempotent)
+            .await
+            .map_err(Error::from)?;
 
-        let text = response.text().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await.map_err(Error::from)?;
         debug!("Raw response: {:#?}", text);
+        if !status.is_success() {
+            return Err(Error::from_http(status, &headers, &text).into());
+        }
 
-        let result: T = serde_json::from_str(&text).map_err(|e| {
-            anyhow::anyhow!("Failed to deserialize response: {}. Body: {}", e, text)
+        let result: T = serde_json::from_str(&text).map_err(|e| Error::Decode {
+            message: format!("Failed to deserialize response: {}. Body: {}", e, text),
         })?;
 
         Ok(result)
     }
 
+    //send an https post and return the raw response so callers can inspect headers and status.
+    //Requests with side effects pass idempotent false so they are only retried when the server rejected them.
+    pub async fn post_raw_request(
+        &self,
+        url: String,
+        headers: Option<reqwest::header::HeaderMap>,
+        body: serde_json::Value,
+        idempotent: bool,
+    ) -> Result<reqwest::Response> {
+        debug!("Url: {}", url);
+        let mut request = self.client.post(url).headers(self.headers.clone());
+
+        if let Some(h) = headers {
+            request = request.headers(h);
+        }
+
+        let response = self
+            .send(request.json(&body), idempotent)
+            .await
+            .map_err(Error::from)?;
+        Ok(response)
+    }
+
+    //send an https get and return the raw response
+    pub async fn get_raw_request(
+        &self,
+        url: String,
+        headers: Option<reqwest::header::HeaderMap>,
+    ) -> Result<reqwest::Response> {
+        debug!("Url: {}", url);
+        let mut request = self.client.get(url).headers(self.headers.clone());
+
+        if let Some(h) = headers {
+            request = request.headers(h);
+        }
+
+        let response = self.send(request, true).await.map_err(Error::from)?;
+        Ok(response)
+    }
+
+    // the retries happen before the stream starts, a broken stream is not resent
     pub async fn post_stream_request(
         &self,
         url: String,
         headers: Option<reqwest::header::HeaderMap>,
         body: serde_json::Value,
+        idempotent: bool,
     ) -> reqwest::Result<reqwest::Response> {
 
         debug!("Url: {}", url);
-        let mut request = self.client.post(url);
+        let mut request = self.client.post(url).headers(self.headers.clone());
 
         if let Some(h) = headers {
             request = request.headers(h);
         }
 
         // debug!("Body: {:#?}", &body);
-        let res = request.json(&body).send().await?;
+        let res = self.send(request.json(&body), idempotent).await?;
         Ok(res)
     }
 }
@@ -0,0 +1,327 @@
+use reqwest::{header::HeaderMap, StatusCode};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RETRY_MAX_ATTEMPTS: u32 = 3;
+const RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const RETRY_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const RETRY_MAX_SERVER_DELAY: Duration = Duration::from_secs(60);
+// anthropic answers 529 when overloaded
+const STATUS_OVERLOADED: u16 = 529;
+
+// RetryPolicy decides when and how long to wait before a request is sent again.
+//
+// Requests are retried on connection failures, 408, 429, 503 and 529 (the anthropic 503),
+// which the server answers before doing any work. Idempotent requests are also retried
+// on timeouts, 500, 502 and 504, where the server may have processed the request.
+// The wait is the server hint from Retry-After, retry-after-ms or the rate limit reset
+// headers when present, otherwise a jittered exponential backoff.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // attempts including the first one, 1 disables retries
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    // full jitter, the backoff is a random duration up to the computed one
+    pub jitter: bool,
+    // give up instead of waiting when the server asks for a longer wait
+    pub max_server_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: RETRY_MAX_ATTEMPTS,
+            initial_backoff: RETRY_INITIAL_BACKOFF,
+            max_backoff: RETRY_MAX_BACKOFF,
+            multiplier: 2.0,
+            jitter: true,
+            max_server_delay: RETRY_MAX_SERVER_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Self::default()
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(1)
+    }
+
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_max_server_delay(mut self, max_server_delay: Duration) -> Self {
+        self.max_server_delay = max_server_delay;
+        self
+    }
+
+    pub fn should_retry_status(&self, status: StatusCode, idempotent: bool) -> bool {
+        match status.as_u16() {
+            408 | 429 | 503 | STATUS_OVERLOADED => true,
+            500 | 502 | 504 => idempotent,
+            _ => false,
+        }
+    }
+
+    pub fn should_retry_error(&self, error: &reqwest::Error, idempotent: bool) -> bool {
+        if error.is_connect() {
+            return true;
+        }
+        idempotent && error.is_timeout()
+    }
+
+    // backoff returns the wait before the next attempt, attempt starts at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(30) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        if self.jitter {
+            Duration::from_secs_f64(backoff * fastrand::f64())
+        } else {
+            Duration::from_secs_f64(backoff)
+        }
+    }
+
+    // delay returns the wait before the next attempt, or None when the server
+    // asks to wait longer than max_server_delay
+    pub fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Option<Duration> {
+        match headers.and_then(server_delay) {
+            Some(delay) if delay > self.max_server_delay => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+// server_delay reads the wait the server asks for.
+// Retry-After and retry-after-ms win, otherwise the latest reset of the exhausted rate limits.
+pub fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = header_str(headers, "retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    if let Some(value) = header_str(headers, "retry-after") {
+        if let Ok(seconds) = value.parse::<f64>() {
+            return Some(Duration::from_secs_f64(seconds.max(0.0)));
+        }
+        if let Ok(date) = httpdate::parse_http_date(value) {
+            return Some(date.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+
+    let mut delay: Option<Duration> = None;
+    // anthropic sends RFC 3339 timestamps
+    for limit in ["requests", "tokens", "input-tokens", "output-tokens"] {
+        let remaining = header_str(headers, &format!("anthropic-ratelimit-{}-remaining", limit));
+        let reset = header_str(headers, &format!("anthropic-ratelimit-{}-reset", limit));
+        if let (Some("0"), Some(reset)) = (remaining, reset) {
+            if let Some(reset) = parse_rfc3339(reset) {
+                let wait = reset.duration_since(SystemTime::now()).unwrap_or_default();
+                delay = delay.max(Some(wait));
+            }
+        }
+    }
+    // openai sends durations like 1s, 6m0s or 20ms
+    for limit in ["requests", "tokens"] {
+        let remaining = header_str(headers, &format!("x-ratelimit-remaining-{}", limit));
+        let reset = header_str(headers, &format!("x-ratelimit-reset-{}", limit));
+        if let (Some("0"), Some(reset)) = (remaining, reset) {
+            if let Some(wait) = parse_duration(reset) {
+                delay = delay.max(Some(wait));
+            }
+        }
+    }
+    delay
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim())
+}
+
+// parse_duration parses go style durations: 1h2m3.5s, 6m0s, 20ms
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    let mut parsed = false;
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let seconds = match c {
+            'h' => amount * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                amount / 1000.0
+            }
+            'm' => amount * 60.0,
+            's' => amount,
+            _ => return None,
+        };
+        total += seconds;
+        parsed = true;
+    }
+    if !number.is_empty() || !parsed {
+        return None;
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+// parse_rfc3339 parses UTC timestamps like 2025-01-01T12:00:30Z, fractions are ignored
+fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date, time) = value.split_once('T')?;
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let time = time.split(['+', '.']).next()?;
+    let mut time = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    // days from civil, Howard Hinnant
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    use axum::{
+        extract::State,
+        http::StatusCode,
+        response::{IntoResponse, Response},
+        routing::post,
+        Router,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::http::HttpClient;
+
+    type Script = Arc<Vec<(u16, Option<&'static str>)>>;
+
+    // answers each attempt with the next (status, Retry-After) of the script, the last one is repeated
+    async fn answer(State((script, attempts)): State<(Script, Arc<AtomicUsize>)>) -> Response {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+        let (status, retry_after) = script[attempt.min(script.len() - 1)];
+        let status = StatusCode::from_u16(status).unwrap();
+        match retry_after {
+            Some(retry_after) => (status, [("Retry-After", retry_after)]).into_response(),
+            None => (status, "{}").into_response(),
+        }
+    }
+
+    // stub returns the url of a local server playing the script and its attempt counter
+    async fn stub(script: Vec<(u16, Option<&'static str>)>) -> (String, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route("/", post(answer))
+            .with_state((Arc::new(script), attempts.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (format!("http://{}/", address), attempts)
+    }
+
+    fn client(policy: RetryPolicy) -> HttpClient {
+        HttpClient::new().unwrap().with_retry_policy(policy)
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(3)
+            .with_backoff(Duration::from_millis(50), Duration::from_millis(100))
+            .with_jitter(false)
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limit_and_unavailable() {
+        let (url, attempts) = stub(vec![(429, Some("0.2")), (503, None), (200, None)]).await;
+        let start = Instant::now();
+        let response = client(policy())
+            .post_raw_request(url, None, json!({}), false)
+            .await
+            .unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        // 200ms asked by Retry-After, then the 100ms backoff of the second attempt
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_server_asks_too_long() {
+        let (url, attempts) = stub(vec![(429, Some("120")), (200, None)]).await;
+        let policy = policy().with_max_server_delay(Duration::from_secs(1));
+        let start = Instant::now();
+        let response = client(policy)
+            .post_raw_request(url, None, json!({}), true)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 429);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_attempts() {
+        let (url, attempts) = stub(vec![(503, None)]).await;
+        let response = client(policy())
+            .post_raw_request(url, None, json!({}), false)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 503);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn server_errors_only_retried_when_idempotent() {
+        let (url, attempts) = stub(vec![(500, None), (200, None)]).await;
+        let response = client(policy())
+            .post_raw_request(url.clone(), None, json!({}), false)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 500);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let response = client(policy())
+            .post_raw_request(url, None, json!({}), true)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -2,3 +2,6 @@ pub mod providers;
 pub mod agent;
 pub mod http;
 pub mod capabilities;
+pub mod error;
+
+pub use error::Error;
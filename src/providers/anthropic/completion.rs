@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+
+use anyhow::Result;
 use async_trait::async_trait;
 use eventsource_stream::Eventsource;
 use futures::StreamExt;
@@ -9,14 +11,16 @@ use crate::{
         client::completion::{CompletionStreamResponse, LlmClient},
         completion::{
             request::CompletionRequest,
-            response::{CompletionChunkResponse, CompletionResponse, CompletionResponseContent},
+            response::{CompletionResponse, CompletionResponseContent, FinishReason, Usage},
+            stream::{event_stream, CompletionStreamEvent},
         }, tools::request::ToolCallRequest,
     },
-    http::HttpClient,
+    error::Error,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
     providers::anthropic::{
         ANTHROPIC_BASE_URL, ANTHROPIC_VERSION, request::AnthropicCompletionRequest, response::{
-            AnthropicChunkResponse, AnthropicCompletionResponse,
-            AnthropicCompletionResponseContent::{Text, ToolUse},
+            finish_reason, AnthropicChunkResponse, AnthropicCompletionResponse, AnthropicUsage,
+            AnthropicCompletionResponseContent::{self, Other, RedactedThinking, Text, Thinking, ToolUse},
         }
     },
 };
@@ -32,13 +36,24 @@ pub struct AnthropicClient {
 
 impl AnthropicClient {
     pub fn new(api_key: String) -> Result<Self> {
+        Self::with_config(api_key, &ClientConfig::default())
+    }
+
+    // with_config creates the client with a shared http configuration, e.g. a gateway base url
+    pub fn with_config(api_key: String, config: &ClientConfig) -> Result<Self> {
         Ok(Self {
             api_key: api_key,
             anthropic_version: ANTHROPIC_VERSION.to_string(),
-            base_url: ANTHROPIC_BASE_URL.to_string(),
-            http_client: HttpClient::new()?,
+            base_url: config.base_url_or(ANTHROPIC_BASE_URL),
+            http_client: HttpClient::from_config(config)?,
         })
     }
+
+    // set the retry policy for the requests to the provider
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
 }
 
 #[async_trait]
@@ -50,38 +65,32 @@ impl LlmClient for AnthropicClient {
         headers.insert("x-api-key", self.api_key.parse()?);
         headers.insert("anthropic-version", self.anthropic_version.parse()?);
 
+        let format_tool = request.response_format.as_ref().map(|f| f.name().to_string());
         let arequest = AnthropicCompletionRequest::new(request)?;
         debug!("AnthropicCompletionRequest {:#?}", arequest);
 
         let body = serde_json::json!(arequest);
         let aresponse = self
             .http_client
-            .post_request::<AnthropicCompletionResponse>(url, Some(headers), body)
+            .post_request::<AnthropicCompletionResponse>(url, Some(headers), body, false)
             .await?;
 
         debug!("Response: {:#?}", aresponse);
 
-        let mut rcontents: Vec<CompletionResponseContent> = Vec::new();
-        for content in aresponse.content {
-            match content {
-                Text { text } => {
-                    let rcontent = CompletionResponseContent::Text(text);
-                    rcontents.push(rcontent);
-                }
-                ToolUse { id, name, input } => {
-                    let rcontent = CompletionResponseContent::ToolCall(ToolCallRequest {
-                        id,
-                        name,
-                        arguments: input,
-                    });
-                    rcontents.push(rcontent);
-                }
-            }
-        }
+        let rcontents = response_contents(aresponse.content, format_tool.as_deref());
 
         let cresponse = CompletionResponse {
             response_id: String::new(),
+            usage: Usage::from(&aresponse.usage),
+            finish_reason: format_finish_reason(
+                finish_reason(aresponse.stop_reason.as_deref()),
+                format_tool.is_some(),
+                rcontents
+                    .iter()
+                    .any(|c| matches!(c, CompletionResponseContent::ToolCall(_))),
+            ),
             contents: rcontents,
+            logprobs: Vec::new(),
         };
 
         Ok(cresponse)
@@ -99,54 +108,269 @@ impl LlmClient for AnthropicClient {
         headers.insert("anthropic-version", self.anthropic_version.parse()?);
         headers.insert("Accept", "text/event-stream".parse()?);
 
+        let format_tool = request.response_format.as_ref().map(|f| f.name().to_string());
         let arequest = AnthropicCompletionRequest::new(request)?;
         let body = serde_json::json!(arequest);
 
         let response = self
             .http_client
-            .post_stream_request(url, Some(headers), body)
-            .await?;
+            .post_stream_request(url, Some(headers), body, false)
+            .await
+            .map_err(Error::from)?;
 
         // debug!("✅ Got response: {:?}", response.error_for_status());
-        if response.status() == 400 {
-            let error_body = response.text().await?;
-            error!("❌ API ERROR BODY: {}", error_body);
-            return Err(anyhow!("Bad request: {}", error_body));
+        if !response.status().is_success() {
+            let error = Error::from_response(response).await;
+            error!("❌ API ERROR: {}", error);
+            return Err(error.into());
         }
         debug!("response: {:#?}", response);
 
+        // message_start carries the id and input usage, message_delta the stop reason and
+        // output usage, content_block_stop ends a tool call only for the tool_use blocks
+        let mut response_id = String::new();
+        let mut usage = AnthropicUsage::default();
+        let mut stop_reason = None;
+        let mut tool_blocks: HashSet<i32> = HashSet::new();
+        // the response format tool is streamed as text, the text written before it is held
+        // back and only sent when the model answers without the tool
+        let mut format_blocks: HashSet<i32> = HashSet::new();
+        let mut held_text = String::new();
+        let mut has_format = false;
+        let mut has_tool_calls = false;
+
         let stream = response
             .bytes_stream()
             .eventsource() // ← Parses SSE format
-            .map(|event_result| -> anyhow::Result<CompletionChunkResponse> {
-                let event = event_result?;
+            .map(move |event_result| -> anyhow::Result<Vec<CompletionStreamEvent>> {
+                let event = event_result.map_err(|e| Error::Transport {
+                    message: e.to_string(),
+                })?;
 
                 debug!("event: {:#?}", &event);
                 // event.data contains the JSON string
                 let chunk: AnthropicChunkResponse =
                     serde_json::from_str(&event.data).map_err(|e| {
-                        anyhow!(format!(
-                            "AnthropicChunkResponse error: {:?} for data {:?}",
-                            e, &event.data
-                        ))
+                        Error::Decode {
+                            message: format!("AnthropicChunkResponse error: {:?} for data {:?}", e, &event.data),
+                        }
                     })?;
 
-                // Transform to CompletionChunkResponse
-                match chunk.r#type.as_str() {
-                    "content_block_delta" => {
-                        let text = chunk.clone().delta.and_then(|d| d.text).unwrap_or_default();
-                        let thinking = &chunk.delta.and_then(|d| d.thinking).unwrap_or_default();
-
-                        Ok(CompletionChunkResponse::content(
-                            text.to_string(),
-                            thinking.to_string(),
-                        ))
+                // Transform to CompletionStreamEvent
+                let index = chunk.index.unwrap_or_default();
+                let events = match chunk.r#type.as_str() {
+                    "message_start" => {
+                        if let Some(message) = chunk.message {
+                            response_id = message.id;
+                            if let Some(musage) = message.usage {
+                                usage = musage;
+                            }
+                        }
+                        vec![]
                     }
-                    "message_stop" => Ok(CompletionChunkResponse::stop(String::new())),
-                    _ => Ok(CompletionChunkResponse::default()),
-                }
+                    "content_block_start" => match chunk.content_block {
+                        Some(block)
+                            if block.r#type.as_deref() == Some("tool_use")
+                                && block.name.is_some()
+                                && block.name == format_tool =>
+                        {
+                            format_blocks.insert(index);
+                            has_format = true;
+                            held_text.clear();
+                            vec![]
+                        }
+                        Some(block) if block.r#type.as_deref() == Some("tool_use") => {
+                            tool_blocks.insert(index);
+                            has_tool_calls = true;
+                            let mut events = Vec::new();
+                            // the text comes before the tool calls it introduces
+                            if !held_text.is_empty() {
+                                events.push(CompletionStreamEvent::TextDelta {
+                                    text: std::mem::take(&mut held_text),
+                                });
+                            }
+                            events.push(CompletionStreamEvent::ToolCallStart {
+                                index,
+                                id: block.id.unwrap_or_default(),
+                                name: block.name.unwrap_or_default(),
+                            });
+                            events
+                        }
+                        // redacted thinking arrives whole, without deltas
+                        Some(block) if block.r#type.as_deref() == Some("redacted_thinking") => {
+                            vec![CompletionStreamEvent::RedactedThinking {
+                                data: block.data.unwrap_or_default(),
+                            }]
+                        }
+                        _ => vec![],
+                    },
+                    "content_block_delta" => match chunk.delta {
+                        Some(delta) => {
+                            if let Some(text) = delta.partial_json.clone().filter(|_| format_blocks.contains(&index)) {
+                                vec![CompletionStreamEvent::TextDelta { text }]
+                            } else if let Some(arguments) = delta.partial_json {
+                                vec![CompletionStreamEvent::ToolCallArgsDelta { index, arguments }]
+                            } else if let Some(text) = delta.text {
+                                if has_format {
+                                    vec![]
+                                } else if format_tool.is_some() {
+                                    held_text.push_str(&text);
+                                    vec![]
+                                } else {
+                                    vec![CompletionStreamEvent::TextDelta { text }]
+                                }
+                            } else if let Some(thinking) = delta.thinking {
+                                vec![CompletionStreamEvent::ThinkingDelta { thinking }]
+                            } else if let Some(signature) = delta.signature {
+                                vec![CompletionStreamEvent::ThinkingSignature { signature, id: None }]
+                            } else {
+                                vec![]
+                            }
+                        }
+                        None => vec![],
+                    },
+                    "content_block_stop" => {
+                        format_blocks.remove(&index);
+                        if tool_blocks.remove(&index) {
+                            vec![CompletionStreamEvent::ToolCallEnd { index }]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    "message_delta" => {
+                        stop_reason = chunk.delta.and_then(|d| d.stop_reason);
+                        if let Some(dusage) = chunk.usage {
+                            usage.output_tokens = dusage.output_tokens;
+                        }
+                        vec![CompletionStreamEvent::Usage(Usage::from(&usage))]
+                    }
+                    "message_stop" => {
+                        let mut events = Vec::new();
+                        if !held_text.is_empty() {
+                            events.push(CompletionStreamEvent::TextDelta {
+                                text: std::mem::take(&mut held_text),
+                            });
+                        }
+                        events.push(CompletionStreamEvent::Stop {
+                            response_id: response_id.clone(),
+                            reason: format_finish_reason(
+                                finish_reason(stop_reason.as_deref()),
+                                format_tool.is_some(),
+                                has_tool_calls,
+                            ),
+                        });
+                        events
+                    }
+                    "error" => {
+                        let message = chunk
+                            .error
+                            .map(|e| format!("{}: {}", e.r#type, e.message))
+                            .unwrap_or_else(|| event.data.clone());
+                        vec![CompletionStreamEvent::Error { message }]
+                    }
+                    _ => vec![],
+                };
+                Ok(events)
             });
 
-        Ok(Box::pin(stream))
+        Ok(event_stream(stream))
+    }
+}
+
+// response_contents converts the content blocks of a response.
+// The input of the response format tool is the response, the text written next to it
+// when the tool is not forced is left out.
+fn response_contents(
+    contents: Vec<AnthropicCompletionResponseContent>,
+    format_tool: Option<&str>,
+) -> Vec<CompletionResponseContent> {
+    let has_format = contents
+        .iter()
+        .any(|c| matches!(c, ToolUse { name, .. } if Some(name.as_str()) == format_tool));
+
+    let mut rcontents: Vec<CompletionResponseContent> = Vec::new();
+    for content in contents {
+        match content {
+            Text { .. } if has_format => {}
+            Text { text } => {
+                let rcontent = CompletionResponseContent::Text(text);
+                rcontents.push(rcontent);
+            }
+            ToolUse { name, input, .. } if Some(name.as_str()) == format_tool => {
+                rcontents.push(CompletionResponseContent::Text(input.to_string()));
+            }
+            ToolUse { id, name, input } => {
+                let rcontent = CompletionResponseContent::ToolCall(ToolCallRequest {
+                    id,
+                    name,
+                    arguments: input,
+                });
+                rcontents.push(rcontent);
+            }
+            Thinking { thinking, signature } => {
+                rcontents.push(CompletionResponseContent::Thought {
+                    text: thinking,
+                    signature: Some(signature),
+                    id: None,
+                });
+            }
+            RedactedThinking { data } => {
+                rcontents.push(CompletionResponseContent::RedactedThought { data });
+            }
+            Other => {}
+        }
+    }
+    rcontents
+}
+
+// format_finish_reason reports a call of the response format tool alone as a stop
+fn format_finish_reason(reason: FinishReason, has_format: bool, has_tool_calls: bool) -> FinishReason {
+    match reason {
+        FinishReason::ToolUse if has_format && !has_tool_calls => FinishReason::Stop,
+        reason => reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const FORMAT_TOOL: &str = "quote";
+
+    fn contents(blocks: Value) -> Vec<CompletionResponseContent> {
+        let blocks: Vec<AnthropicCompletionResponseContent> =
+            serde_json::from_value(blocks).unwrap();
+        response_contents(blocks, Some(FORMAT_TOOL))
+    }
+
+    #[test]
+    fn format_tool_input_is_the_response() {
+        // without a forced tool the model may write a preamble before the format tool
+        let contents = contents(json!([
+            {"type": "text", "text": "Here is the quote:"},
+            {"type": "tool_use", "id": "toolu_1", "name": FORMAT_TOOL, "input": {"symbol": "AAPL"}},
+        ]));
+        assert_eq!(contents.len(), 1);
+        let CompletionResponseContent::Text(text) = &contents[0] else {
+            panic!("unexpected content {:?}", contents);
+        };
+        let value: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(value, json!({"symbol": "AAPL"}));
+    }
+
+    #[test]
+    fn text_kept_without_format_tool() {
+        let contents = contents(json!([
+            {"type": "text", "text": "Checking"},
+            {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Paris"}},
+        ]));
+        assert!(
+            matches!(&contents[0], CompletionResponseContent::Text(text) if text == "Checking")
+        );
+        assert!(
+            matches!(&contents[1], CompletionResponseContent::ToolCall(call) if call.name == "weather")
+        );
     }
 }
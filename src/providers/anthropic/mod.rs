@@ -1,3 +1,7 @@
+use anyhow::Result;
+
+use crate::capabilities::completion::request::Sampling;
+
 pub mod completion;
 pub mod response;
 pub mod request;
@@ -6,5 +10,15 @@ pub mod request;
 
 pub const LLM: &str = "Anthropic";
 pub const MODEL_CLAUDE_SONNET_4_5: &str = "claude-sonnet-4-5";
+pub const MODEL_CLAUDE_OPUS_4_5: &str = "claude-opus-4-5";
+pub const MODEL_CLAUDE_HAIKU_4_5: &str = "claude-haiku-4-5";
 const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
-const ANTHROPIC_VERSION: &str = "2023-06-01";
\ No newline at end of file
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const SAMPLING_PARAMETERS: &[&str] = &["top_p", "top_k", "stop_sequences"];
+// extended thinking only works with the default temperature, top_p and top_k
+const THINKING_IGNORED_PARAMETERS: &[&str] = &["top_p", "top_k"];
+
+// check_sampling fails when a sampling parameter is not supported by the messages api
+pub(crate) fn check_sampling(_model: &str, sampling: &Sampling) -> Result<()> {
+    sampling.check(LLM, SAMPLING_PARAMETERS)
+}
@@ -1,55 +1,135 @@
-use crate::capabilities::completion::{message::Message, request::CompletionRequest};
+use crate::{
+    capabilities::completion::{
+        message::Message,
+        request::{CompletionRequest, Reasoning, ReasoningEffort},
+    },
+    providers::anthropic::{check_sampling, LLM, THINKING_IGNORED_PARAMETERS},
+};
 use anyhow::{Context, Result};
 use serde::Serialize;
 use serde_json::Value;
 
+// thinking budgets of the effort levels, anthropic requires at least 1024 tokens
+const THINKING_BUDGET_MIN: u32 = 1024;
+const THINKING_BUDGET_LOW: u32 = 4096;
+const THINKING_BUDGET_MEDIUM: u32 = 10000;
+const THINKING_BUDGET_HIGH: u32 = 32000;
+
 #[derive(Debug, Serialize)]
 pub struct AnthropicCompletionRequest {
     model: String,
     max_tokens: i32,
-    temperature: f32,
+    // thinking only works with the default temperature, top_p and top_k
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
     messages: Vec<AnthropicCompletionRequestMessage>,
     system: Option<String>,
     stream: bool,
     pub tools: Vec<AnthropicToolDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<AnthropicThinking>,
 }
 
 #[derive(Debug, Serialize)]
-pub struct AnthropicCompletionRequestSystem {
+pub struct AnthropicThinking {
     r#type: String,
-    text: String,
+    budget_tokens: u32,
 }
 
-#[derive(Serialize, Debug)]
-#[serde(untagged)] // Removes the variant wrapper
-pub enum AnthropicCompletionRequestMessage {
-    Content {
-        role: String,
-        content: String,
-    },
-    ToolUse {
-        role: String,
-        content: Vec<AnthropicCompletionRequestToolUse>,
-    },
-    ToolResult {
-        role: String,
-        content: Vec<AnthropicCompletionRequestToolResult>,
-    },
+impl AnthropicThinking {
+    // new enables thinking with the budget, or the budget of the effort level capped to half of
+    // max_tokens so the answer has room. Returns None when neither is set.
+    // The budget has to be less than max_tokens.
+    fn new(reasoning: &Reasoning, max_tokens: i32) -> Result<Option<Self>> {
+        let budget_tokens = match (reasoning.budget_tokens, reasoning.effort) {
+            (Some(budget_tokens), _) => budget_tokens,
+            (None, Some(effort)) => {
+                let budget_tokens = match effort {
+                    ReasoningEffort::Minimal => THINKING_BUDGET_MIN,
+                    ReasoningEffort::Low => THINKING_BUDGET_LOW,
+                    ReasoningEffort::Medium => THINKING_BUDGET_MEDIUM,
+                    ReasoningEffort::High => THINKING_BUDGET_HIGH,
+                };
+                budget_tokens
+                    .min(max_tokens.max(0) as u32 / 2)
+                    .max(THINKING_BUDGET_MIN)
+            }
+            (None, None) => return Ok(None),
+        };
+        if budget_tokens < THINKING_BUDGET_MIN {
+            return Err(anyhow::anyhow!(
+                "The thinking budget of {} tokens is below the minimum of {}",
+                budget_tokens,
+                THINKING_BUDGET_MIN
+            ));
+        }
+        if budget_tokens as i64 >= max_tokens as i64 {
+            return Err(anyhow::anyhow!(
+                "The thinking budget of {} tokens must be less than max_tokens {}",
+                budget_tokens,
+                max_tokens
+            ));
+        }
+        Ok(Some(AnthropicThinking {
+            r#type: "enabled".to_string(),
+            budget_tokens,
+        }))
+    }
 }
 
 #[derive(Debug, Serialize)]
-pub struct AnthropicCompletionRequestToolUse {
+pub struct AnthropicToolChoice {
     r#type: String,
-    input: Value,
-    id: String,
     name: String,
 }
 
 #[derive(Debug, Serialize)]
-pub struct AnthropicCompletionRequestToolResult {
+pub struct AnthropicCompletionRequestSystem {
     r#type: String,
-    tool_use_id: String,
-    content: String,
+    text: String,
+}
+
+// AnthropicCompletionRequestMessage is one turn, consecutive messages of the same role
+// are merged into the blocks of one turn as the api requires alternating roles
+#[derive(Serialize, Debug)]
+pub struct AnthropicCompletionRequestMessage {
+    role: String,
+    content: Vec<AnthropicCompletionRequestContent>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicCompletionRequestContent {
+    Text {
+        text: String,
+    },
+    // thinking is sent back unchanged with its signature
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
 }
 
 #[derive(Debug, Serialize)]
@@ -61,79 +141,86 @@ pub struct AnthropicToolDefinition {
 
 impl AnthropicCompletionRequest {
     pub fn new(request: CompletionRequest) -> Result<AnthropicCompletionRequest> {
+        check_sampling(&request.model, &request.sampling)?;
         let mut messages: Vec<AnthropicCompletionRequestMessage> = Vec::new();
 
-        let mut tool_result_contents = Vec::new();
-        let mut tool_use_contents = Vec::new();
-
+        // the blocks keep the order of the messages
         for message in request.messages {
-            match message {
-                Message::Thought { content: _ } => {}
-                Message::User {
+            let (role, content) = match message {
+                // thinking without a signature, e.g. from another provider, can not be sent back
+                Message::Thought {
                     content,
-                    response_id: _,
-                } => {
-                    messages.push(AnthropicCompletionRequestMessage::Content {
-                        role: "user".to_string(),
-                        content: content,
-                    });
-                    // messages.push(amessage);
+                    signature: Some(signature),
+                    ..
+                } => (
+                    "assistant",
+                    AnthropicCompletionRequestContent::Thinking {
+                        thinking: content,
+                        signature,
+                    },
+                ),
+                Message::Thought { .. } => continue,
+                Message::RedactedThought { data } => (
+                    "assistant",
+                    AnthropicCompletionRequestContent::RedactedThinking { data },
+                ),
+                // empty text blocks are rejected
+                Message::User { content, .. } | Message::Assistant { content, .. }
+                    if content.is_empty() =>
+                {
+                    continue
                 }
-                Message::Assistant {
-                    content,
-                    response_id: _,
-                } => {
-                    messages.push(AnthropicCompletionRequestMessage::Content {
-                        role: "assistant".to_string(),
-                        content: content,
-                    });
+                Message::User { content, .. } => {
+                    ("user", AnthropicCompletionRequestContent::Text { text: content })
+                }
+                Message::Assistant { content, .. } => {
+                    ("assistant", AnthropicCompletionRequestContent::Text { text: content })
                 }
                 Message::ToolCall {
                     arguments,
                     call_id,
                     name,
                 } => {
-                    let value = serde_json::from_str(&arguments)
-                        .context("Failed to serialize arguments for OpenAI")?;
-
-                    let content = AnthropicCompletionRequestToolUse {
-                        r#type: "tool_use".to_string(),
-                        input: value,
-                        id: call_id,
-                        name: name,
-                    };
-                    tool_use_contents.push(content);
+                    // arguments the model sent as invalid json are replaced, the tool output holds the error
+                    let input = serde_json::from_str(&arguments)
+                        .ok()
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| serde_json::json!({}));
+                    (
+                        "assistant",
+                        AnthropicCompletionRequestContent::ToolUse {
+                            id: call_id,
+                            name,
+                            input,
+                        },
+                    )
                 }
                 Message::ToolOutput {
                     call_id,
                     output,
                     name: _,
+                    is_error,
                 } => {
-                    let arg_string = serde_json::to_string(&output)
-                        .context("Failed to serialize arguments for Anthropic")?;
-
-                    let content = AnthropicCompletionRequestToolResult {
-                        r#type: "tool_result".to_string(),
-                        content: arg_string,
-                        tool_use_id: call_id,
-                    };
-                    tool_result_contents.push(content);
+                    let content = serde_json::to_string(&output)
+                        .context("Failed to serialize tool output for Anthropic")?;
+                    (
+                        "user",
+                        AnthropicCompletionRequestContent::ToolResult {
+                            tool_use_id: call_id,
+                            content,
+                            is_error,
+                        },
+                    )
                 }
-            }
-        }
-
-        if tool_use_contents.len() > 0 {
-            messages.push(AnthropicCompletionRequestMessage::ToolUse {
-                role: "assistant".to_string(),
-                content: tool_use_contents,
-            });
-        }
+            };
 
-        if tool_result_contents.len() > 0 {
-            messages.push(AnthropicCompletionRequestMessage::ToolResult {
-                role: "user".to_string(),
-                content: tool_result_contents,
-            });
+            match messages.last_mut() {
+                Some(last) if last.role == role => last.content.push(content),
+                _ => messages.push(AnthropicCompletionRequestMessage {
+                    role: role.to_string(),
+                    content: vec![content],
+                }),
+            }
         }
 
         let mut atools = Vec::new();
@@ -145,16 +232,287 @@ impl AnthropicCompletionRequest {
             };
             atools.push(atool);
         }
+
+        let thinking = match &request.reasoning {
+            Some(reasoning) => AnthropicThinking::new(reasoning, request.max_tokens)?,
+            None => None,
+        };
+
+        // Anthropic has no json mode, the response format is a tool the model is forced to call.
+        // With other tools the model picks the tool, forcing it would end the tool loop.
+        // Thinking does not allow forcing a tool, the model is left to pick it.
+        let mut tool_choice = None;
+        if let Some(format) = request.response_format {
+            if atools.is_empty() && thinking.is_none() {
+                tool_choice = Some(AnthropicToolChoice {
+                    r#type: "tool".to_string(),
+                    name: format.name().to_string(),
+                });
+            }
+            atools.push(AnthropicToolDefinition {
+                name: format.name().to_string(),
+                description: "Respond with the final answer as the input of this tool".to_string(),
+                input_schema: format.schema(),
+            });
+        }
+
+        let mut sampling = request.sampling;
+        if thinking.is_some() {
+            sampling.warn_ignored(&format!("{} extended thinking", LLM), THINKING_IGNORED_PARAMETERS);
+            sampling.top_p = None;
+            sampling.top_k = None;
+        }
+
         let arequest = AnthropicCompletionRequest {
             max_tokens: request.max_tokens,
             messages: messages,
             model: request.model,
             system: request.system,
-            temperature: request.temperature,
+            temperature: match thinking {
+                Some(_) => None,
+                None => Some(request.temperature),
+            },
+            top_p: sampling.top_p,
+            top_k: sampling.top_k,
+            stop_sequences: sampling.stop_sequences,
             stream: request.stream,
             tools: atools,
+            tool_choice,
+            thinking,
         };
 
         Ok(arequest)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::capabilities::completion::request::{ConversationState, Sampling};
+
+    fn request(messages: Vec<Message>) -> CompletionRequest {
+        CompletionRequest {
+            model: "claude-haiku-4-5".to_string(),
+            system: Some("Be brief".to_string()),
+            messages,
+            temperature: 0.5,
+            max_tokens: 4096,
+            stream: false,
+            definitions: Vec::new(),
+            response_format: None,
+            conversation_state: ConversationState::default(),
+            reasoning: None,
+            sampling: Sampling::default(),
+        }
+    }
+
+    fn body(request: CompletionRequest) -> Value {
+        serde_json::to_value(AnthropicCompletionRequest::new(request).unwrap()).unwrap()
+    }
+
+    fn user(content: &str) -> Message {
+        Message::User {
+            content: content.to_string(),
+            response_id: None,
+        }
+    }
+
+    fn assistant(content: &str) -> Message {
+        Message::Assistant {
+            content: content.to_string(),
+            response_id: None,
+        }
+    }
+
+    fn tool_call(call_id: &str, symbol: &str) -> Message {
+        Message::ToolCall {
+            arguments: json!({ "symbol": symbol }).to_string(),
+            call_id: call_id.to_string(),
+            name: "get_quote".to_string(),
+        }
+    }
+
+    fn tool_output(call_id: &str, price: f64) -> Message {
+        Message::ToolOutput {
+            call_id: call_id.to_string(),
+            output: json!({ "price": price }),
+            name: "get_quote".to_string(),
+            is_error: false,
+        }
+    }
+
+    #[test]
+    fn text_turn() {
+        assert_eq!(
+            body(request(vec![user("Hello")])),
+            json!({
+                "model": "claude-haiku-4-5",
+                "max_tokens": 4096,
+                "temperature": 0.5,
+                "messages": [
+                    {"role": "user", "content": [{"type": "text", "text": "Hello"}]}
+                ],
+                "system": "Be brief",
+                "stream": false,
+                "tools": []
+            })
+        );
+    }
+
+    #[test]
+    fn thinking_tool_use_and_result() {
+        let mut request = request(vec![
+            user("Price of AAPL?"),
+            Message::Thought {
+                content: "I need the quote".to_string(),
+                signature: Some("sig-1".to_string()),
+                id: None,
+            },
+            tool_call("toolu_1", "AAPL"),
+            tool_output("toolu_1", 187.5),
+            assistant("AAPL is at 187.5"),
+        ]);
+        request.reasoning = Some(Reasoning::new().with_budget_tokens(2048));
+
+        assert_eq!(
+            body(request),
+            json!({
+                "model": "claude-haiku-4-5",
+                "max_tokens": 4096,
+                "messages": [
+                    {"role": "user", "content": [{"type": "text", "text": "Price of AAPL?"}]},
+                    {"role": "assistant", "content": [
+                        {"type": "thinking", "thinking": "I need the quote", "signature": "sig-1"},
+                        {"type": "tool_use", "id": "toolu_1", "name": "get_quote", "input": {"symbol": "AAPL"}}
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "tool_result", "tool_use_id": "toolu_1", "content": "{\"price\":187.5}"}
+                    ]},
+                    {"role": "assistant", "content": [{"type": "text", "text": "AAPL is at 187.5"}]}
+                ],
+                "system": "Be brief",
+                "stream": false,
+                "tools": [],
+                "thinking": {"type": "enabled", "budget_tokens": 2048}
+            })
+        );
+    }
+
+    #[test]
+    fn thinking_drops_sampling_parameters() {
+        let mut request = request(vec![user("Hello")]);
+        request.reasoning = Some(Reasoning::new().with_budget_tokens(2048));
+        request.sampling = Sampling::new()
+            .with_top_p(0.9)
+            .with_top_k(40)
+            .with_stop_sequences(&["END"]);
+        assert_eq!(
+            request.sampling.warn_ignored(LLM, THINKING_IGNORED_PARAMETERS),
+            ["top_p", "top_k"]
+        );
+
+        let body = body(request);
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("top_p").is_none());
+        assert!(body.get("top_k").is_none());
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+    }
+
+    #[test]
+    fn redacted_thinking_sent_back() {
+        let body = body(request(vec![
+            user("Price of AAPL?"),
+            Message::RedactedThought {
+                data: "encrypted".to_string(),
+            },
+            tool_call("toolu_1", "AAPL"),
+        ]));
+        assert_eq!(
+            body["messages"][1],
+            json!({"role": "assistant", "content": [
+                {"type": "redacted_thinking", "data": "encrypted"},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_quote", "input": {"symbol": "AAPL"}}
+            ]})
+        );
+    }
+
+    #[test]
+    fn parallel_tool_calls() {
+        let body = body(request(vec![
+            user("Prices of AAPL and MSFT?"),
+            tool_call("toolu_1", "AAPL"),
+            tool_call("toolu_2", "MSFT"),
+            tool_output("toolu_1", 187.5),
+            tool_output("toolu_2", 410.0),
+        ]));
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "user", "content": [{"type": "text", "text": "Prices of AAPL and MSFT?"}]},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_quote", "input": {"symbol": "AAPL"}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "get_quote", "input": {"symbol": "MSFT"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "{\"price\":187.5}"},
+                    {"type": "tool_result", "tool_use_id": "toolu_2", "content": "{\"price\":410.0}"}
+                ]}
+            ])
+        );
+    }
+
+    #[test]
+    fn consecutive_same_role_messages_merged() {
+        let body = body(request(vec![
+            user("Hello"),
+            user("Price of AAPL?"),
+            tool_call("toolu_1", "AAPL"),
+            tool_output("toolu_1", 187.5),
+            user("And in euros?"),
+        ]));
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Hello"},
+                    {"type": "text", "text": "Price of AAPL?"}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_quote", "input": {"symbol": "AAPL"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "{\"price\":187.5}"},
+                    {"type": "text", "text": "And in euros?"}
+                ]}
+            ])
+        );
+    }
+
+    #[test]
+    fn empty_text_and_unsigned_thoughts_skipped() {
+        let body = body(request(vec![
+            user("Hello"),
+            // the tool loop ends a turn without text with an empty assistant message
+            Message::Assistant {
+                content: String::new(),
+                response_id: Some("resp_1".to_string()),
+            },
+            Message::Thought {
+                content: "from another provider".to_string(),
+                signature: None,
+                id: None,
+            },
+            assistant("Hi"),
+            user(""),
+        ]));
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "user", "content": [{"type": "text", "text": "Hello"}]},
+                {"role": "assistant", "content": [{"type": "text", "text": "Hi"}]}
+            ])
+        );
+    }
+}
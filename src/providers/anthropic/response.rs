@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::capabilities::completion::response::{FinishReason, Usage};
+
 
 
 #[derive(Debug, Deserialize)]
@@ -8,6 +10,20 @@ pub struct AnthropicCompletionResponse {
     pub model: String,
     pub role: String,
     pub content: Vec<AnthropicCompletionResponseContent>,
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: AnthropicUsage,
+}
+
+pub(super) fn finish_reason(stop_reason: Option<&str>) -> FinishReason {
+    match stop_reason {
+        None | Some("end_turn") => FinishReason::Stop,
+        Some("max_tokens") => FinishReason::MaxTokens,
+        Some("tool_use") => FinishReason::ToolUse,
+        Some("stop_sequence") => FinishReason::StopSequence,
+        Some("refusal") => FinishReason::ContentFilter,
+        Some(reason) => FinishReason::Other(reason.to_string()),
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -25,7 +41,22 @@ pub enum AnthropicCompletionResponseContent {
         input: Value,
         name: String
     },
-    
+
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking {
+        data: String,
+    },
+
+    // blocks of newer api versions
+    #[serde(other)]
+    Other,
+
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -33,12 +64,59 @@ pub struct AnthropicChunkResponse {
     pub r#type: String,
     pub index: Option<i32>,
     pub delta: Option<AnthropicChunkResponseDelta>,
+    pub content_block: Option<AnthropicChunkResponseContentBlock>,
+    pub message: Option<AnthropicChunkResponseMessage>,
+    pub usage: Option<AnthropicUsage>,
+    pub error: Option<AnthropicChunkResponseError>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnthropicChunkResponseMessage {
+    pub id: String,
+    pub usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AnthropicUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+// anthropic reports input_tokens without the cache reads and writes
+impl From<&AnthropicUsage> for Usage {
+    fn from(usage: &AnthropicUsage) -> Self {
+        Usage {
+            input_tokens: usage.input_tokens
+                + usage.cache_creation_input_tokens
+                + usage.cache_read_input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: usage.cache_read_input_tokens,
+            cache_creation_tokens: usage.cache_creation_input_tokens,
+            reasoning_tokens: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnthropicChunkResponseError {
+    pub r#type: String,
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AnthropicChunkResponseContentBlock {
     pub r#type: Option<String>,
     pub text: Option<String>,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    // encrypted thinking of a redacted_thinking block
+    pub data: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -46,5 +124,33 @@ pub struct AnthropicChunkResponseDelta {
     pub r#type: Option<String>,
     pub text: Option<String>,
     pub thinking: Option<String>,
+    pub signature: Option<String>,
+    pub partial_json: Option<String>,
+    pub stop_reason: Option<String>,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_thinking_parsed() {
+        let response: AnthropicCompletionResponse = serde_json::from_str(
+            r#"{
+                "model": "claude-sonnet-4-5",
+                "role": "assistant",
+                "content": [
+                    {"type": "redacted_thinking", "data": "encrypted"},
+                    {"type": "text", "text": "Hi"}
+                ],
+                "stop_reason": "end_turn"
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &response.content[0],
+            AnthropicCompletionResponseContent::RedactedThinking { data } if data == "encrypted"
+        ));
+    }
+}
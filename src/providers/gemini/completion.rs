@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+
+use anyhow::Result;
 use async_trait::async_trait;
 use eventsource_stream::Eventsource;
 use futures::StreamExt;
@@ -9,13 +11,15 @@ use crate::{
         client::completion::{CompletionStreamResponse, LlmClient},
         completion::{
             request::CompletionRequest,
-            response::{CompletionChunkResponse, CompletionResponse, CompletionResponseContent},
+            response::{CompletionResponse, CompletionResponseContent, Usage},
+            stream::{event_stream, CompletionStreamEvent},
         }, tools::request::ToolCallRequest,
     },
-    http::HttpClient,
+    error::Error,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
     providers::gemini::{
         GEMINI_BASE_URL, request::GeminiInteractionsRequest, response::{
-            GeminiInteractionsChunkResponse, GeminiInteractionsResponse,
+            finish_reason, GeminiInteractionsChunkResponse, GeminiInteractionsResponse,
             GeminiInteractionsResponseOutput::{FunctionCall, Text, Thought},
         }
     },
@@ -31,13 +35,24 @@ pub struct GeminiClient {
 
 impl GeminiClient {
     pub fn new(api_key: String) -> Result<Self> {
+        Self::with_config(api_key, &ClientConfig::default())
+    }
+
+    // with_config creates the client with a shared http configuration, e.g. a gateway base url
+    pub fn with_config(api_key: String, config: &ClientConfig) -> Result<Self> {
         Ok(Self {
             api_key: api_key,
-            base_url: GEMINI_BASE_URL.to_string(),
-            http_client: HttpClient::new()?,
+            base_url: config.base_url_or(GEMINI_BASE_URL),
+            http_client: HttpClient::from_config(config)?,
         })
     }
 
+    // set the retry policy for the requests to the provider
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
+
     // async fn complete_generate_content(
     //     &self,
     //     request: CompletionRequest,
@@ -79,7 +94,7 @@ impl GeminiClient {
         let body = serde_json::json!(grequest);
         let gresponse = self
             .http_client
-            .post_request::<GeminiInteractionsResponse>(url, Some(headers), body)
+            .post_request::<GeminiInteractionsResponse>(url, Some(headers), body, false)
             .await?;
 
         debug!("GeminiCompletionResponse: {:#?}", gresponse);
@@ -105,17 +120,27 @@ impl GeminiClient {
                     });
                     rcontents.push(rcontent);
                 }
-                Thought { signature } => {
-                    let rcontent: CompletionResponseContent =
-                        CompletionResponseContent::Thought(signature);
+                Thought { signature, summary } => {
+                    let text: Vec<String> = summary.into_iter().filter_map(|s| s.text).collect();
+                    let rcontent = CompletionResponseContent::Thought {
+                        text: text.join("\n\n"),
+                        signature,
+                        id: None,
+                    };
                     rcontents.push(rcontent);
                 }
             }
         }
 
+        let has_tool_calls = rcontents
+            .iter()
+            .any(|c| matches!(c, CompletionResponseContent::ToolCall(_)));
         let cresponse = CompletionResponse {
             response_id: id,
             contents: rcontents,
+            usage: Usage::from(&gresponse.usage),
+            finish_reason: finish_reason(Some(gresponse.status.as_str()), has_tool_calls),
+            logprobs: Vec::new(),
         };
 
         Ok(cresponse)
@@ -143,61 +168,145 @@ impl LlmClient for GeminiClient {
         debug!("Body: {:#?}", body);
         let response = self
             .http_client
-            .post_stream_request(url, Some(headers), body)
-            .await?;
+            .post_stream_request(url, Some(headers), body, false)
+            .await
+            .map_err(Error::from)?;
 
-        if response.status() == 400 {
-            let error_body = response.text().await?;
-            error!("❌ API ERROR BODY: {}", error_body);
-            return Err(anyhow!("Bad request: {}", error_body));
+        if !response.status().is_success() {
+            let error = Error::from_response(response).await;
+            error!("❌ API ERROR: {}", error);
+            return Err(error.into());
         }
 
         // debug!("Gemini Request: {:#?}", grequest);
+        // function calls may be announced by content.start or only by their delta,
+        // content.stop ends a tool call only for the function call contents
+        let mut tool_contents: HashSet<i32> = HashSet::new();
+        let mut has_tool_calls = false;
+
         let stream = response
             .bytes_stream()
             .eventsource() // ← Parses SSE format
-            .map(|event_result| -> anyhow::Result<CompletionChunkResponse> {
-                let event = event_result?;
+            .map(move |event_result| -> anyhow::Result<Vec<CompletionStreamEvent>> {
+                let event = event_result.map_err(|e| Error::Transport {
+                    message: e.to_string(),
+                })?;
                 debug!("event: {:#?}", event.data);
 
                 if event.data.contains("[DONE]") {
-                    return Ok(CompletionChunkResponse::default());
+                    return Ok(vec![]);
                 }
 
                 let chunk: GeminiInteractionsChunkResponse = serde_json::from_str(&event.data)
                     .map_err(|e| {
-                        anyhow!(format!(
-                            "GeminiChunkResponse error: {:?} for data {:?}",
-                            e, &event.data
-                        ))
+                        Error::Decode {
+                            message: format!("GeminiChunkResponse error: {:?} for data {:?}", e, &event.data),
+                        }
                     })?;
 
                 // debug!("chunk: {:#?}", chunk);
-                match chunk.event_type.as_str() {
-                    "content.start" => Ok(CompletionChunkResponse::default()),
-                    "content.delta" => {
-                        if let Some(delta) = chunk.delta {
-                            if let Some(text) = delta.text {
-                                Ok(CompletionChunkResponse::content(text, String::new()))
-                            } else {
-                                Ok(CompletionChunkResponse::default())
+                let index = chunk.index.unwrap_or_default();
+                let events = match chunk.event_type.as_str() {
+                    "content.start" => match chunk.content {
+                        Some(content) if content.r#type == "function_call" => {
+                            has_tool_calls = true;
+                            tool_contents.insert(index);
+                            vec![CompletionStreamEvent::ToolCallStart {
+                                index,
+                                id: content.id.unwrap_or_default(),
+                                name: content.name.unwrap_or_default(),
+                            }]
+                        }
+                        Some(content) if content.r#type == "thought" => match content.signature {
+                            Some(signature) => {
+                                vec![CompletionStreamEvent::ThinkingSignature { signature, id: None }]
                             }
-                        } else {
-                            Ok(CompletionChunkResponse::default())
+                            None => vec![],
+                        },
+                        _ => vec![],
+                    },
+                    "content.delta" => match chunk.delta {
+                        Some(delta) if delta.r#type == "function_call" => {
+                            let mut events = Vec::new();
+                            has_tool_calls = true;
+                            if tool_contents.insert(index) {
+                                events.push(CompletionStreamEvent::ToolCallStart {
+                                    index,
+                                    id: delta.id.unwrap_or_default(),
+                                    name: delta.name.unwrap_or_default(),
+                                });
+                            }
+                            // function call deltas carry the complete arguments object
+                            if let Some(arguments) = delta.arguments {
+                                events.push(CompletionStreamEvent::ToolCallArgs {
+                                    index,
+                                    arguments: arguments.to_string(),
+                                });
+                            }
+                            events
                         }
-                    }
-                    "content.stop" => Ok(CompletionChunkResponse::default()),
-                    "interaction.complete" => {
-                        if let Some(interaction) = chunk.interaction {
-                            Ok(CompletionChunkResponse::stop(interaction.id))
+                        Some(delta) if delta.r#type == "thought_signature" => match delta.signature {
+                            Some(signature) => {
+                                vec![CompletionStreamEvent::ThinkingSignature { signature, id: None }]
+                            }
+                            None => vec![],
+                        },
+                        Some(delta) if delta.r#type == "thought_summary" => {
+                            let thinking = delta
+                                .content
+                                .as_ref()
+                                .and_then(|c| c.get("text"))
+                                .and_then(|t| t.as_str())
+                                .map(|t| t.to_string())
+                                .or(delta.text);
+                            match thinking {
+                                Some(thinking) => {
+                                    vec![CompletionStreamEvent::ThinkingDelta { thinking }]
+                                }
+                                None => vec![],
+                            }
+                        }
+                        Some(delta) => match delta.text {
+                            Some(text) => vec![CompletionStreamEvent::TextDelta { text }],
+                            None => vec![],
+                        },
+                        None => vec![],
+                    },
+                    "content.stop" => {
+                        if tool_contents.remove(&index) {
+                            vec![CompletionStreamEvent::ToolCallEnd { index }]
                         } else {
-                            Ok(CompletionChunkResponse::default())
+                            vec![]
                         }
                     }
-                    _ => Ok(CompletionChunkResponse::default()),
-                }
+                    "interaction.complete" => match chunk.interaction {
+                        Some(interaction) => {
+                            let mut events = Vec::new();
+                            if let Some(usage) = interaction.usage {
+                                events.push(CompletionStreamEvent::Usage(Usage::from(&usage)));
+                            }
+                            events.push(CompletionStreamEvent::Stop {
+                                response_id: interaction.id,
+                                reason: finish_reason(
+                                    interaction.status.as_deref(),
+                                    has_tool_calls,
+                                ),
+                            });
+                            events
+                        }
+                        None => vec![],
+                    },
+                    "error" => vec![CompletionStreamEvent::Error {
+                        message: chunk
+                            .error
+                            .map(|e| e.message)
+                            .unwrap_or_else(|| event.data.clone()),
+                    }],
+                    _ => vec![],
+                };
+                Ok(events)
             });
 
-        Ok(Box::pin(stream))
+        Ok(event_stream(stream))
     }
 }
@@ -1,6 +1,6 @@
 use crate::{
     capabilities::{client::embeddings::EmbeddingClient, embeddings::{BatchResult, Embedding}},
-    http::HttpClient,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
     providers::gemini::{
             GEMINI_BASE_URL, request::GeminiEmbeddingsRequest, response::GeminiEmbeddingsResponse
         },
@@ -18,12 +18,23 @@ pub struct GeminiEmbeddingClient {
 
 impl GeminiEmbeddingClient {
     pub fn new(api_key: &str) -> Result<Self> {
+        Self::with_config(api_key, &ClientConfig::default())
+    }
+
+    // with_config creates the client with a shared http configuration, e.g. a gateway base url
+    pub fn with_config(api_key: &str, config: &ClientConfig) -> Result<Self> {
         Ok(GeminiEmbeddingClient {
             api_key: api_key.to_string(),
-            base_url: GEMINI_BASE_URL.to_string(),
-            http_client: HttpClient::new()?,
+            base_url: config.base_url_or(GEMINI_BASE_URL),
+            http_client: HttpClient::from_config(config)?,
         })
     }
+
+    // set the retry policy for the requests to the provider
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
 }
 
 #[async_trait]
@@ -42,7 +53,7 @@ impl EmbeddingClient for GeminiEmbeddingClient {
         let body = serde_json::json!(request);
         let response = self
             .http_client
-            .post_request::<GeminiEmbeddingsResponse>(url, Some(headers), body)
+            .post_request::<GeminiEmbeddingsResponse>(url, Some(headers), body, true)
             .await?;
         debug!("Response: {:#?}", response.embedding.values.len());
 
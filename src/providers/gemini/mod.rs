@@ -1,3 +1,7 @@
+use anyhow::Result;
+
+use crate::capabilities::completion::request::Sampling;
+
 pub mod completion;
 pub mod request;
 pub mod response;
@@ -5,5 +9,18 @@ pub mod embeddings;
 
 pub const LLM: &str = "Gemini";
 pub const MODEL_GEMINI_3_FLASH_PREVIEW: &str = "gemini-3-flash-preview";
+pub const MODEL_GEMINI_3_PRO_PREVIEW: &str = "gemini-3-pro-preview";
+pub const MODEL_GEMINI_2_5_FLASH: &str = "gemini-2.5-flash";
 pub const MODEL_GEMINI_EMBEDDING_001: &str = "gemini-embedding-001";
 const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+const SAMPLING_PARAMETERS: &[&str] = &["top_p", "stop_sequences", "seed"];
+
+// gemini 2.5 models think within a token budget, later models by level
+pub(crate) fn uses_thinking_budget(model: &str) -> bool {
+    model.starts_with("gemini-2.5")
+}
+
+// check_sampling fails when a sampling parameter is not supported by the interactions api
+pub(crate) fn check_sampling(_model: &str, sampling: &Sampling) -> Result<()> {
+    sampling.check(LLM, SAMPLING_PARAMETERS)
+}
@@ -1,11 +1,12 @@
 use serde::Serialize;
 use serde_json::Value;
 use anyhow::{Context, Result};
+use tracing::warn;
 
 use crate::{
     capabilities::{completion::{
-        message::Message, request::CompletionRequest,
-    }, tools::tool::ToolDefinition}, providers::gemini::{MODEL_GEMINI_3_FLASH_PREVIEW, MODEL_GEMINI_EMBEDDING_001},
+        message::{new_turn, Message}, request::{CompletionRequest, ConversationState, ResponseFormat},
+    }, tools::tool::ToolDefinition}, providers::gemini::{check_sampling, uses_thinking_budget, MODEL_GEMINI_EMBEDDING_001},
 };
 
 #[derive(Debug, Serialize)]
@@ -15,171 +16,225 @@ pub struct GeminiInteractionsRequest {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     previous_interaction_id: Option<String>,
+    // false for stateless conversations, the interaction is not kept by gemini
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<bool>,
     system_instruction: String,
     stream: bool,
     pub tools: Vec<ToolDefinition>,
-}
-
-#[derive(Serialize, Debug)]
-#[serde(untagged)] // Removes the variant wrapper
-pub enum GeminiCompletionRequestInput {
-    Content {
-        role: String,
-        content: String,
-    },
-    Thought {
-        role:String,
-        r#type: String,
-        // thought_signature: GeminiCompletionRequestThoughtSignature,
-        thought_signature: String,
-        content: String
-    },
-
-    FunctionCall {
-        role: String,
-        content: Vec<GeminiCompletionRequestFunctionCall>
-    },
-    FunctionCallResult {
-        role: String,
-        content: Vec<GeminiCompletionRequestFunctionResult>
-    },
+    // the json schema of the response, with application/json as mime type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    generation_config: GeminiInteractionsGenerationConfig,
 }
 
 #[derive(Debug, Serialize)]
-pub struct GeminiCompletionRequestThoughtSignature {
-    signature: String,
+pub struct GeminiInteractionsGenerationConfig {
+    temperature: f32,
+    max_output_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_level: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_summaries: Option<String>,
 }
 
-
 #[derive(Debug, Serialize)]
-pub struct GeminiCompletionRequestFunctionCall {
-    r#type: String,
-    arguments: Value,
-    id: String,
-    name: String,
+pub struct GeminiThinkingConfig {
+    thinking_budget: u32,
 }
 
-#[derive(Debug, Serialize)]
-pub struct GeminiCompletionRequestFunctionResult {
-    r#type: String,
-    call_id: String,
-    result: String,
-    name: String
+impl GeminiInteractionsGenerationConfig {
+    // new maps the sampling and reasoning settings. The budget goes to thinking_config on
+    // gemini 2.5 models, later models think by level and have no budget.
+    fn new(request: &CompletionRequest) -> Result<Self> {
+        check_sampling(&request.model, &request.sampling)?;
+        let reasoning = request.reasoning.clone().unwrap_or_default();
+        let uses_budget = uses_thinking_budget(&request.model);
+        let thinking_config = match reasoning.budget_tokens {
+            Some(thinking_budget) if uses_budget => Some(GeminiThinkingConfig { thinking_budget }),
+            Some(_) => {
+                warn!(
+                    "Gemini model {} thinks by level and has no thinking budget, budget_tokens is ignored",
+                    request.model
+                );
+                None
+            }
+            None => None,
+        };
+        let thinking_level = match reasoning.effort {
+            Some(effort) if !uses_budget => Some(effort.as_str().to_string()),
+            Some(_) => {
+                warn!(
+                    "Gemini model {} thinks by budget and has no thinking level, the effort is ignored",
+                    request.model
+                );
+                None
+            }
+            None => None,
+        };
+        Ok(GeminiInteractionsGenerationConfig {
+            temperature: request.temperature,
+            max_output_tokens: request.max_tokens,
+            top_p: request.sampling.top_p,
+            seed: request.sampling.seed,
+            stop_sequences: request.sampling.stop_sequences.clone(),
+            thinking_level,
+            thinking_config,
+            thinking_summaries: reasoning.summary.then(|| "auto".to_string()),
+        })
+    }
 }
 
+// GeminiCompletionRequestInput is a turn of the conversation, user or model
+#[derive(Serialize, Debug)]
+pub struct GeminiCompletionRequestInput {
+    role: String,
+    content: Vec<GeminiCompletionRequestInputContent>,
+}
 
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeminiCompletionRequestInputContent {
+    Text {
+        text: String,
+    },
+    // the signature of the model thoughts, sent back so the model keeps its reasoning
+    Thought {
+        signature: String,
+    },
+    FunctionCall {
+        arguments: Value,
+        id: String,
+        name: String,
+    },
+    FunctionResult {
+        call_id: String,
+        result: String,
+        name: String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+}
 
 impl GeminiInteractionsRequest {
     pub fn new(request: CompletionRequest) -> Result<Self> {
-        let mut id: Option<String> = None;
-
-        let mut inputs = Vec::new();
-        let mut function_result_contents = Vec::new();
-        let mut function_call_contents = Vec::new();
-
-        // create the input with blank content
-        let mut input = GeminiCompletionRequestInput::Content {
-            role: "user".to_string(),
-            content: String::new(),
-        };
-
-        for message in request.messages {
-            match message {
-
-                Message::Thought {content:_} => {
-                    // let input = GeminiCompletionRequestInput::Thought { 
-                    //     role: "model".to_string(),
-                    //     r#type: "thought_signature".to_string(),
-                    //     // thought_signature: GeminiCompletionRequestThoughtSignature { signature: content.clone() },
-                    //     thought_signature: content,
-                    //     content: "what is the weather in paris and San Fransicso".to_string()
-                    // };
-                    // inputs.push(input);
-                }
-
-                Message::User {
-                    content,
-                    response_id: _,
-                } => {
-                    
-                    input = GeminiCompletionRequestInput::Content {
-                        role: "user".to_string(),
-                        content: content,
-                    };                    
-                    // inputs.push(input);
-                }
-                Message::Assistant {
-                    content:_,
-                    response_id,
-                } => {
-                    id = response_id;
-                }
-
-                Message::ToolCall {
-                    arguments,
-                    call_id,
-                    name,
-                } => {
-
-                    let value = serde_json::from_str(&arguments)
-                            .context("Failed to serialize arguments for Gemini")?;
-
-                    let input = GeminiCompletionRequestFunctionCall {
-                        arguments: value,
-                        id: call_id,    
-                        name: name,                    
-                        r#type: "function_call".to_string(),
-                    };
-                    function_call_contents.push(input);
-
-                }
-                Message::ToolOutput { call_id, output, name } => {
-
-                    let arg_string = serde_json::to_string(&output)
-                        .context("Failed to serialize arguments for Gemini")?;
-
-                    let input = GeminiCompletionRequestFunctionResult {
-                        result: arg_string,
-                        call_id: call_id,    
-                        name: name,
-                        r#type: "function_result".to_string(),
-                    };
-                    function_result_contents.push(input);
-                }
+        let generation_config = GeminiInteractionsGenerationConfig::new(&request)?;
+        let (inputs, id, store) = match request.conversation_state {
+            // the rest of the conversation is referred to by the id of the previous interaction
+            ConversationState::ServerManaged => {
+                let (id, messages) = new_turn(request.messages);
+                (turn_inputs(messages)?, id, None)
             }
-        }
-
-        inputs.push(input);
-        
-        if function_call_contents.len() > 0 {
-            let input = GeminiCompletionRequestInput::FunctionCall { 
-                role: "model".to_string(),
-                content: function_call_contents,
-            };
-            inputs.push(input);
-        }
+            ConversationState::Stateless => (turn_inputs(request.messages)?, None, Some(false)),
+        };
 
-        if function_result_contents.len() > 0 {
-            let input = GeminiCompletionRequestInput::FunctionCallResult { 
-                role: "user".to_string(),
-                content: function_result_contents,
-            };
-            inputs.push(input);
-        }
-        
         let grequest = GeminiInteractionsRequest {
-            model: MODEL_GEMINI_3_FLASH_PREVIEW.to_string(),
+            model: request.model,
             input: inputs,
             system_instruction: request.system.unwrap_or(String::new()),
             previous_interaction_id: id,
+            store,
             stream: request.stream,
             tools: request.definitions,
+            response_mime_type: request
+                .response_format
+                .as_ref()
+                .map(|_| "application/json".to_string()),
+            response_format: match request.response_format {
+                Some(ResponseFormat::JsonSchema { schema, .. }) => Some(schema),
+                _ => None,
+            },
+            generation_config,
         };
 
         Ok(grequest)
     }
 }
 
+// turn_inputs converts the messages into user and model turns in the order of the messages.
+// Consecutive messages of the same role are merged into one turn, thoughts without a signature are
+// dropped and the response ids are ignored.
+fn turn_inputs(messages: Vec<Message>) -> Result<Vec<GeminiCompletionRequestInput>> {
+    let mut inputs: Vec<GeminiCompletionRequestInput> = Vec::new();
+    for message in messages {
+        let role = match &message {
+            Message::User { .. } | Message::ToolOutput { .. } => "user",
+            Message::Assistant { .. }
+            | Message::Thought { .. }
+            | Message::RedactedThought { .. }
+            | Message::ToolCall { .. } => "model",
+        };
+        let Some(content) = content(message)? else {
+            continue;
+        };
+        match inputs.last_mut() {
+            Some(input) if input.role == role => input.content.push(content),
+            _ => inputs.push(GeminiCompletionRequestInput {
+                role: role.to_string(),
+                content: vec![content],
+            }),
+        }
+    }
+    Ok(inputs)
+}
+
+// content converts a message into the content of a turn, None for the messages Gemini does not take:
+// empty text, thoughts without a signature and the redacted thinking of Anthropic
+fn content(message: Message) -> Result<Option<GeminiCompletionRequestInputContent>> {
+    let content = match message {
+        Message::User { content, .. } | Message::Assistant { content, .. } if content.is_empty() => {
+            return Ok(None)
+        }
+        Message::User { content, .. } | Message::Assistant { content, .. } => {
+            GeminiCompletionRequestInputContent::Text { text: content }
+        }
+        Message::Thought {
+            signature: Some(signature),
+            ..
+        } => GeminiCompletionRequestInputContent::Thought { signature },
+        Message::Thought { .. } | Message::RedactedThought { .. } => return Ok(None),
+        Message::ToolCall {
+            arguments,
+            call_id,
+            name,
+        } => {
+            // arguments the model sent as invalid json are replaced, the tool output holds the error
+            let arguments = serde_json::from_str(&arguments)
+                .ok()
+                .filter(Value::is_object)
+                .unwrap_or_else(|| serde_json::json!({}));
+            GeminiCompletionRequestInputContent::FunctionCall {
+                arguments,
+                id: call_id,
+                name,
+            }
+        }
+        Message::ToolOutput {
+            call_id,
+            output,
+            name,
+            is_error,
+        } => GeminiCompletionRequestInputContent::FunctionResult {
+            result: serde_json::to_string(&output)
+                .context("Failed to serialize arguments for Gemini")?,
+            call_id,
+            name,
+            is_error,
+        },
+    };
+    Ok(Some(content))
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiCompletionRequest {
@@ -288,4 +343,148 @@ impl GeminiEmbeddingsRequest {
             content: GeminiEmbeddingsRequestContent { parts}
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::capabilities::completion::request::{Reasoning, ReasoningEffort, Sampling};
+
+    fn request(model: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: model.to_string(),
+            system: None,
+            messages: Vec::new(),
+            temperature: 1.0,
+            max_tokens: 8192,
+            stream: false,
+            definitions: Vec::new(),
+            response_format: None,
+            conversation_state: ConversationState::default(),
+            reasoning: Some(Reasoning::new().with_budget_tokens(2048)),
+            sampling: Sampling::default(),
+        }
+    }
+
+    #[test]
+    fn thinking_budget_only_for_budget_models() {
+        let thinking = |model: &str| {
+            let mut request = request(model);
+            request.reasoning = Some(
+                Reasoning::new()
+                    .with_budget_tokens(2048)
+                    .with_effort(ReasoningEffort::Low),
+            );
+            let config = GeminiInteractionsGenerationConfig::new(&request).unwrap();
+            let config = serde_json::to_value(config).unwrap();
+            (
+                config.get("thinking_config").cloned(),
+                config.get("thinking_level").cloned(),
+            )
+        };
+
+        assert_eq!(
+            thinking("gemini-2.5-flash"),
+            (Some(json!({"thinking_budget": 2048})), None)
+        );
+        assert_eq!(thinking("gemini-3-pro-preview"), (None, Some(json!("low"))));
+    }
+
+    fn conversation(messages: Vec<Message>, conversation_state: ConversationState) -> Value {
+        let mut request = request("gemini-3-pro-preview");
+        request.messages = messages;
+        request.conversation_state = conversation_state;
+        request.reasoning = None;
+        serde_json::to_value(GeminiInteractionsRequest::new(request).unwrap()).unwrap()
+    }
+
+    // the messages of a tool turn as the tool loop keeps them, then the final answer
+    fn history() -> Vec<Message> {
+        let user = |content: &str| Message::User {
+            content: content.to_string(),
+            response_id: None,
+        };
+        let model = |content: &str, id: &str| Message::Assistant {
+            content: content.to_string(),
+            response_id: Some(id.to_string()),
+        };
+        vec![
+            user("Price of AAPL?"),
+            Message::Thought {
+                content: String::new(),
+                signature: Some("gsig-1".to_string()),
+                id: None,
+            },
+            model("", "int_1"),
+            Message::ToolCall {
+                arguments: json!({"symbol": "AAPL"}).to_string(),
+                call_id: "call_1".to_string(),
+                name: "get_quote".to_string(),
+            },
+            Message::ToolOutput {
+                call_id: "call_1".to_string(),
+                output: json!({"price": 187.5}),
+                name: "get_quote".to_string(),
+                is_error: false,
+            },
+            model("AAPL is at 187.5", "int_2"),
+            user("And MSFT?"),
+        ]
+    }
+
+    #[test]
+    fn stateless_sends_the_full_history() {
+        assert_eq!(
+            conversation(history(), ConversationState::Stateless),
+            json!({
+                "model": "gemini-3-pro-preview",
+                "input": [
+                    {"role": "user", "content": [{"type": "text", "text": "Price of AAPL?"}]},
+                    {"role": "model", "content": [
+                        {"type": "thought", "signature": "gsig-1"},
+                        {"type": "function_call", "arguments": {"symbol": "AAPL"}, "id": "call_1", "name": "get_quote"}
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "function_result", "call_id": "call_1", "result": "{\"price\":187.5}", "name": "get_quote"}
+                    ]},
+                    {"role": "model", "content": [{"type": "text", "text": "AAPL is at 187.5"}]},
+                    {"role": "user", "content": [{"type": "text", "text": "And MSFT?"}]}
+                ],
+                "store": false,
+                "system_instruction": "",
+                "stream": false,
+                "tools": [],
+                "generation_config": {"temperature": 1.0, "max_output_tokens": 8192}
+            })
+        );
+    }
+
+    #[test]
+    fn server_managed_refers_to_the_previous_interaction() {
+        assert_eq!(
+            conversation(history(), ConversationState::ServerManaged),
+            json!({
+                "model": "gemini-3-pro-preview",
+                "input": [{"role": "user", "content": [{"type": "text", "text": "And MSFT?"}]}],
+                "previous_interaction_id": "int_2",
+                "system_instruction": "",
+                "stream": false,
+                "tools": [],
+                "generation_config": {"temperature": 1.0, "max_output_tokens": 8192}
+            })
+        );
+
+        // within the tool loop only the function results follow the interaction
+        let mut history = history();
+        history.truncate(5);
+        let body = conversation(history, ConversationState::ServerManaged);
+        assert_eq!(body["previous_interaction_id"], "int_1");
+        assert_eq!(
+            body["input"],
+            json!([{"role": "user", "content": [
+                {"type": "function_result", "call_id": "call_1", "result": "{\"price\":187.5}", "name": "get_quote"}
+            ]}])
+        );
+    }
+}
@@ -1,11 +1,26 @@
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::capabilities::completion::response::{FinishReason, Usage};
+
 #[derive(Debug, Deserialize)]
 pub struct GeminiInteractionsResponse {
     pub id: String,
     pub outputs: Vec<GeminiInteractionsResponseOutput>,
     pub status: String,
+    #[serde(default)]
+    pub usage: GeminiInteractionsUsage,
+}
+
+// requires_action is the status of an interaction waiting for function results
+pub(super) fn finish_reason(status: Option<&str>, has_tool_calls: bool) -> FinishReason {
+    match status {
+        None | Some("completed") if has_tool_calls => FinishReason::ToolUse,
+        None | Some("completed") => FinishReason::Stop,
+        Some("requires_action") => FinishReason::ToolUse,
+        Some("incomplete") => FinishReason::MaxTokens,
+        Some(status) => FinishReason::Other(status.to_string()),
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -14,8 +29,13 @@ pub enum GeminiInteractionsResponseOutput {
     #[serde(rename = "text")]
     Text { text: String },
 
+    // the summary is only returned when thinking summaries are asked for
     #[serde(rename = "thought")]
-    Thought { signature: String },
+    Thought {
+        signature: Option<String>,
+        #[serde(default)]
+        summary: Vec<GeminiInteractionsResponseSummary>,
+    },
 
     #[serde(rename = "function_call")]
     FunctionCall {
@@ -25,22 +45,69 @@ pub enum GeminiInteractionsResponseOutput {
     },
 }
 
+#[derive(Deserialize, Debug)]
+pub struct GeminiInteractionsResponseSummary {
+    pub text: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GeminiInteractionsChunkResponse {
     pub event_type: String,
+    pub index: Option<i32>,
+    pub content: Option<GeminiInteractionsChunkResponseDelta>,
     pub delta: Option<GeminiInteractionsChunkResponseDelta>,
     pub interaction: Option<GeminiInteractionsChunkResponseInteraction>,
+    pub error: Option<GeminiInteractionsChunkResponseError>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GeminiInteractionsChunkResponseDelta {
     pub r#type: String,
     pub text: Option<String>,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments: Option<Value>,
+    pub signature: Option<String>,
+    // thought summaries carry their text in a nested content object
+    pub content: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GeminiInteractionsChunkResponseInteraction {
     pub id: String,
+    pub status: Option<String>,
+    pub usage: Option<GeminiInteractionsUsage>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GeminiInteractionsUsage {
+    #[serde(default)]
+    pub total_input_tokens: u64,
+    #[serde(default)]
+    pub total_output_tokens: u64,
+    #[serde(default)]
+    pub total_cached_tokens: u64,
+    #[serde(default)]
+    pub total_thought_tokens: u64,
+}
+
+// gemini reports the thought tokens apart from the output tokens
+impl From<&GeminiInteractionsUsage> for Usage {
+    fn from(usage: &GeminiInteractionsUsage) -> Self {
+        Usage {
+            input_tokens: usage.total_input_tokens,
+            output_tokens: usage.total_output_tokens + usage.total_thought_tokens,
+            cached_input_tokens: usage.total_cached_tokens,
+            cache_creation_tokens: 0,
+            reasoning_tokens: usage.total_thought_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeminiInteractionsChunkResponseError {
+    pub code: Option<Value>,
+    pub message: String,
 }
 
 #[derive(Deserialize, Debug)]
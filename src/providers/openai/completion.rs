@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use eventsource_stream::Eventsource;
 use futures::StreamExt;
@@ -10,13 +10,15 @@ use crate::{
         client::completion::{CompletionStreamResponse, LlmClient},
         completion::{
             request::CompletionRequest,
-            response::{CompletionChunkResponse, CompletionResponse, CompletionResponseContent}
+            response::{CompletionResponse, CompletionResponseContent, Usage},
+            stream::{event_stream, CompletionStreamEvent},
         }, tools::request::ToolCallRequest,
     },
-    http::HttpClient,
+    error::Error,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
     providers::openai::{
         OPENAI_BASE_URL, request::OpenAICompletionRequest, response::{
-            OpenAIChunkResponseData, OpenAICompletionResponse,
+            finish_reason, OpenAIChunkResponseData, OpenAICompletionResponse,
             OpenAICompletionResponseOutput::{FunctionCall, Message, Reasoning},
         }
     },
@@ -32,12 +34,23 @@ pub struct OpenAIClient {
 
 impl OpenAIClient {
     pub fn new(api_key: String) -> Result<Self> {
+        Self::with_config(api_key, &ClientConfig::default())
+    }
+
+    // with_config creates the client with a shared http configuration, e.g. a gateway base url
+    pub fn with_config(api_key: String, config: &ClientConfig) -> Result<Self> {
         Ok(Self {
             api_key: api_key,
-            base_url: OPENAI_BASE_URL.to_string(),
-            http_client: HttpClient::new()?,
+            base_url: config.base_url_or(OPENAI_BASE_URL),
+            http_client: HttpClient::from_config(config)?,
         })
     }
+
+    // set the retry policy for the requests to the provider
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
 }
 
 #[async_trait]
@@ -55,12 +68,13 @@ impl LlmClient for OpenAIClient {
         // debug!("Body: {:#?}", body);
         let oresponse = self
             .http_client
-            .post_request::<OpenAICompletionResponse>(url, Some(headers), body)
+            .post_request::<OpenAICompletionResponse>(url, Some(headers), body, false)
             .await?;
 
         debug!("OpenAICompletionResponse: {:#?}", oresponse);
 
         let mut rcontents: Vec<CompletionResponseContent> = Vec::new();
+        let mut logprobs = Vec::new();
         let id = oresponse.id;
 
         for output in oresponse.output {
@@ -70,9 +84,11 @@ impl LlmClient for OpenAIClient {
                     status,
                     content,
                 } => {
-                    if status == "completed" {
+                    // incomplete messages carry the text written before the cut off
+                    if status == "completed" || status == "incomplete" {
                         for content in content {
                             if content.r#type == "output_text" {
+                                logprobs.extend(content.logprobs);
                                 let rcontent= CompletionResponseContent::Text(content.text);
                                 rcontents.push(rcontent);
                                 break;
@@ -87,12 +103,9 @@ impl LlmClient for OpenAIClient {
                     name,
                 } => {
                     if status == "completed" {
-                        let arguments: Value = match serde_json::from_str(arguments.as_str()) {
-                            Ok(c) => c,
-                            Err(e) => {
-                                return Err(anyhow!("Error parsing function arguments: {:#?}", e))
-                            }
-                        };
+                        // invalid json is kept as a string and reported back to the model
+                        let arguments: Value = serde_json::from_str(arguments.as_str())
+                            .unwrap_or(Value::String(arguments));
 
                         let rcontent = CompletionResponseContent::ToolCall(ToolCallRequest {
                             id : call_id,
@@ -102,19 +115,40 @@ impl LlmClient for OpenAIClient {
                         rcontents.push(rcontent);
                     }
                 }
-                Reasoning{
-                    id:_,
-                    summary:_
+                Reasoning {
+                    id,
+                    summary,
+                    encrypted_content,
                 } => {
-                    
+                    let text: Vec<String> = summary.into_iter().map(|s| s.text).collect();
+                    if !text.is_empty() || encrypted_content.is_some() {
+                        rcontents.push(CompletionResponseContent::Thought {
+                            text: text.join("\n\n"),
+                            signature: encrypted_content,
+                            id: Some(id),
+                        });
+                    }
                 }
-                
+
             }
         }
 
+        let has_tool_calls = rcontents
+            .iter()
+            .any(|c| matches!(c, CompletionResponseContent::ToolCall(_)));
         let cresponse = CompletionResponse {
             response_id: id,
             contents: rcontents,
+            usage: Usage::from(&oresponse.usage),
+            finish_reason: finish_reason(
+                oresponse.status.as_deref(),
+                oresponse
+                    .incomplete_details
+                    .as_ref()
+                    .and_then(|d| d.reason.as_deref()),
+                has_tool_calls,
+            ),
+            logprobs,
         };
 
         Ok(cresponse)
@@ -137,52 +171,123 @@ impl LlmClient for OpenAIClient {
         let body = serde_json::json!(request);
         let response = self
             .http_client
-            .post_stream_request(url, Some(headers), body)
-            .await?;
+            .post_stream_request(url, Some(headers), body, false)
+            .await
+            .map_err(Error::from)?;
 
         // debug!("✅ Got response: {:?}", response.error_for_status());
-        if response.status() == 400 {
-            let error_body = response.text().await?;
-            error!("❌ API ERROR BODY: {}", error_body);
-            return Err(anyhow!("Bad request: {}", error_body));
+        if !response.status().is_success() {
+            let error = Error::from_response(response).await;
+            error!("❌ API ERROR: {}", error);
+            return Err(error.into());
         }
 
+        let mut has_tool_calls = false;
         let stream = response
             .bytes_stream()
             .eventsource() // ← Parses SSE format
-            .map(|event_result| -> anyhow::Result<CompletionChunkResponse> {
-                let event = event_result?;
+            .map(move |event_result| -> anyhow::Result<Vec<CompletionStreamEvent>> {
+                let event = event_result.map_err(|e| Error::Transport {
+                    message: e.to_string(),
+                })?;
                 debug!("event: {:#?}", &event);
 
                 let chunk: OpenAIChunkResponseData =
                     serde_json::from_str(&event.data).map_err(|e| {
-                        anyhow!(format!(
-                            "OpenAIChunkResponse error: {:?} for data {:?}",
-                            e, &event.data
-                        ))
+                        Error::Decode {
+                            message: format!("OpenAIChunkResponse error: {:?} for data {:?}", e, &event.data),
+                        }
                     })?;
 
-                match event.event.as_str() {
+                let index = chunk.output_index.unwrap_or_default();
+                let events = match event.event.as_str() {
                     "response.output_text.delta" => {
-                        if let Some(delta) = chunk.delta {
-                            Ok(CompletionChunkResponse::content(delta, String::new()))
-                        } else {
-                            Ok(CompletionChunkResponse::default())
+                        let mut events = Vec::new();
+                        if let Some(text) = chunk.delta {
+                            events.push(CompletionStreamEvent::TextDelta { text });
                         }
+                        if !chunk.logprobs.is_empty() {
+                            events.push(CompletionStreamEvent::Logprobs {
+                                logprobs: chunk.logprobs,
+                            });
+                        }
+                        events
                     }
-                    "response.completed" => {
-                        if let Some(response) = chunk.response {
-                            Ok(CompletionChunkResponse::stop(response.id))
-                        } else {
-                            Ok(CompletionChunkResponse::default())
+                    "response.reasoning_summary_text.delta" => match chunk.delta {
+                        Some(thinking) => vec![CompletionStreamEvent::ThinkingDelta { thinking }],
+                        None => vec![],
+                    },
+                    "response.output_item.added" => match chunk.item {
+                        Some(item) if item.r#type == "function_call" => {
+                            has_tool_calls = true;
+                            vec![CompletionStreamEvent::ToolCallStart {
+                                index,
+                                id: item.call_id.unwrap_or_default(),
+                                name: item.name.unwrap_or_default(),
+                            }]
                         }
+                        _ => vec![],
+                    },
+                    "response.function_call_arguments.delta" => {
+                        vec![CompletionStreamEvent::ToolCallArgsDelta {
+                            index,
+                            arguments: chunk.delta.unwrap_or_default(),
+                        }]
                     }
-                    _ => Ok(CompletionChunkResponse::default()),
-                }
+                    "response.output_item.done" => match chunk.item {
+                        Some(item) if item.r#type == "function_call" => {
+                            vec![CompletionStreamEvent::ToolCallEnd { index }]
+                        }
+                        // the encrypted reasoning of stateless conversations
+                        Some(item) if item.r#type == "reasoning" => match item.encrypted_content {
+                            Some(signature) => vec![CompletionStreamEvent::ThinkingSignature {
+                                signature,
+                                id: item.id,
+                            }],
+                            None => vec![],
+                        },
+                        _ => vec![],
+                    },
+                    "response.completed" | "response.incomplete" => match chunk.response {
+                        Some(response) => {
+                            let mut events = Vec::new();
+                            if let Some(usage) = response.usage {
+                                events.push(CompletionStreamEvent::Usage(Usage::from(&usage)));
+                            }
+                            let reason = finish_reason(
+                                response.status.as_deref(),
+                                response
+                                    .incomplete_details
+                                    .as_ref()
+                                    .and_then(|d| d.reason.as_deref()),
+                                has_tool_calls,
+                            );
+                            events.push(CompletionStreamEvent::Stop {
+                                response_id: response.id,
+                                reason,
+                            });
+                            events
+                        }
+                        None => vec![],
+                    },
+                    "response.failed" => {
+                        let message = chunk
+                            .response
+                            .and_then(|r| r.error)
+                            .map(|e| format!("{}: {}", e.code.unwrap_or_default(), e.message))
+                            .unwrap_or_else(|| event.data.clone());
+                        vec![CompletionStreamEvent::Error { message }]
+                    }
+                    "error" => vec![CompletionStreamEvent::Error {
+                        message: chunk.message.unwrap_or_else(|| event.data.clone()),
+                    }],
+                    _ => vec![],
+                };
+                Ok(events)
             });
 
         debug!("done streaming");
 
-        Ok(Box::pin(stream))
+        Ok(event_stream(stream))
     }
 }
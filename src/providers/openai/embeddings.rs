@@ -3,7 +3,7 @@ use crate::{
         client::embeddings::EmbeddingClient,
         embeddings::{BatchResult, Embedding},
     },
-    http::HttpClient,
+    http::{config::ClientConfig, retry::RetryPolicy, HttpClient},
     providers::openai::{
         request::OpenAIEmbeddingsRequest, response::OpenAIEmbeddingsResponse, OPENAI_BASE_URL,
     },
@@ -21,12 +21,23 @@ pub struct OpenAIEmbeddingClient {
 
 impl OpenAIEmbeddingClient {
     pub fn new(api_key: &str) -> Result<Self> {
+        Self::with_config(api_key, &ClientConfig::default())
+    }
+
+    // with_config creates the client with a shared http configuration, e.g. a gateway base url
+    pub fn with_config(api_key: &str, config: &ClientConfig) -> Result<Self> {
         Ok(OpenAIEmbeddingClient {
             api_key: api_key.to_string(),
-            base_url: OPENAI_BASE_URL.to_string(),
-            http_client: HttpClient::new()?,
+            base_url: config.base_url_or(OPENAI_BASE_URL),
+            http_client: HttpClient::from_config(config)?,
         })
     }
+
+    // set the retry policy for the requests to the provider
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = self.http_client.with_retry_policy(retry_policy);
+        self
+    }
 }
 
 #[async_trait]
@@ -44,7 +55,7 @@ impl EmbeddingClient for OpenAIEmbeddingClient {
 
         let response = self
             .http_client
-            .post_request::<OpenAIEmbeddingsResponse>(url, Some(headers), body)
+            .post_request::<OpenAIEmbeddingsResponse>(url, Some(headers), body, true)
             .await?;
         debug!("Response: {:#?}", response.data.len());
         let embedding = Embedding::new(response.data[0].embedding.clone());
@@ -64,7 +75,7 @@ impl EmbeddingClient for OpenAIEmbeddingClient {
 
         let response = self
             .http_client
-            .post_request::<OpenAIEmbeddingsResponse>(url, Some(headers), body)
+            .post_request::<OpenAIEmbeddingsResponse>(url, Some(headers), body, true)
             .await?;
 
 
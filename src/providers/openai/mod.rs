@@ -1,3 +1,7 @@
+use anyhow::Result;
+
+use crate::capabilities::completion::request::Sampling;
+
 pub mod completion;
 pub mod request;
 pub mod response;
@@ -5,5 +9,37 @@ pub mod embeddings;
 
 pub const LLM: &str = "OpenAI";
 pub const MODEL_GPT_5_NANO: &str = "gpt-5-nano";
+pub const MODEL_GPT_5_MINI: &str = "gpt-5-mini";
+pub const MODEL_GPT_5: &str = "gpt-5";
 pub const MODEL_TEXT_EMBEDDING_3_SMALL: &str = "text-embedding-3-small";
 const OPENAI_BASE_URL: &str = "https://api.openai.com";
+const SAMPLING_PARAMETERS: &[&str] = &["top_p", "logprobs"];
+
+// reasoning models (gpt-5, o-series) only sample with their default temperature
+pub(crate) fn is_reasoning_model(model: &str) -> bool {
+    (model.starts_with("gpt-5") && !model.contains("chat"))
+        || ["o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix))
+}
+
+// check_sampling fails when a sampling parameter is not supported by the responses api or the model
+pub(crate) fn check_sampling(model: &str, sampling: &Sampling) -> Result<()> {
+    match is_reasoning_model(model) {
+        true => sampling.check(&format!("{} model {}", LLM, model), &[]),
+        false => sampling.check(LLM, SAMPLING_PARAMETERS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasoning_models() {
+        for model in ["gpt-5", "gpt-5-mini", "o1", "o3-mini", "o4-mini"] {
+            assert!(is_reasoning_model(model), "{}", model);
+        }
+        for model in ["gpt-5-chat-latest", "gpt-4.1", "omni-moderation-latest", "openai-gpt"] {
+            assert!(!is_reasoning_model(model), "{}", model);
+        }
+    }
+}
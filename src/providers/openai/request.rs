@@ -1,9 +1,10 @@
 use serde::Serialize;
 use anyhow::{Context, Result};
+use tracing::warn;
 
 use crate::{capabilities::{completion::{
-    message::Message, request::CompletionRequest
-}, tools::tool::ToolDefinition}, providers::openai::MODEL_TEXT_EMBEDDING_3_SMALL};
+    message::{new_turn, Message}, request::{CompletionRequest, ConversationState, Reasoning, ResponseFormat}
+}, tools::{schema, tool::ToolDefinition}}, providers::openai::{check_sampling, is_reasoning_model, MODEL_TEXT_EMBEDDING_3_SMALL}};
 
 #[derive(Serialize, Debug)]
 pub struct OpenAICompletionRequest {
@@ -11,13 +12,27 @@ pub struct OpenAICompletionRequest {
     instructions: String,
     // input: String,
     input: Vec<OpenAICompletionRequestMessage>,
+    // false for stateless conversations, the reasoning is sent back encrypted
     store: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     previous_response_id: Option<String>,
     max_output_tokens: i32,
-    reasoning: OpenAICompletionRequestReasoning,
+    // reasoning models only take the default temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u32>,
+    // only for reasoning models, the model default without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<OpenAICompletionRequestReasoning>,
     pub tools: Vec<ToolDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<OpenAICompletionRequestText>,
 }
 
 #[derive(Serialize, Debug)]
@@ -38,21 +53,112 @@ pub enum OpenAICompletionRequestMessage {
         call_id: String,
         output: String,
     },
+    Reasoning {
+        r#type: String,
+        id: String,
+        summary: Vec<OpenAICompletionRequestSummary>,
+        encrypted_content: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+pub struct OpenAICompletionRequestSummary {
+    r#type: String,
+    text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OpenAICompletionRequestText {
+    format: OpenAICompletionRequestFormat,
+}
+
+// json_object needs the word JSON in the instructions or the input
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OpenAICompletionRequestFormat {
+    JsonObject,
+    JsonSchema {
+        name: String,
+        schema: serde_json::Value,
+        strict: bool,
+    },
+}
+
+impl From<ResponseFormat> for OpenAICompletionRequestFormat {
+    fn from(format: ResponseFormat) -> Self {
+        match format {
+            ResponseFormat::Json => OpenAICompletionRequestFormat::JsonObject,
+            ResponseFormat::JsonSchema {
+                name,
+                schema: format_schema,
+                strict,
+            } => OpenAICompletionRequestFormat::JsonSchema {
+                name,
+                schema: match strict {
+                    true => schema::strict(&format_schema),
+                    false => format_schema,
+                },
+                strict,
+            },
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
 pub struct OpenAICompletionRequestReasoning {
-    effort: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+}
+
+// openai reasons by effort level, the thinking budget has no equivalent
+impl From<Reasoning> for OpenAICompletionRequestReasoning {
+    fn from(reasoning: Reasoning) -> Self {
+        if reasoning.budget_tokens.is_some() {
+            warn!("OpenAI does not support a thinking budget, budget_tokens is ignored");
+        }
+        OpenAICompletionRequestReasoning {
+            effort: reasoning.effort.map(|effort| effort.as_str().to_string()),
+            summary: reasoning.summary.then(|| "auto".to_string()),
+        }
+    }
 }
 
 impl OpenAICompletionRequest {
     pub fn new(request: CompletionRequest) -> Result<Self> {
-        let mut id: Option<String> = None;
+        check_sampling(&request.model, &request.sampling)?;
+        let stateless = request.conversation_state == ConversationState::Stateless;
+        // server managed conversations send the messages after the previous response
+        let (id, messages) = match stateless {
+            true => (None, request.messages),
+            false => new_turn(request.messages),
+        };
 
         let mut inputs = Vec::new();
-        for message in request.messages {
+        for message in messages {
             match message {
-                Message::Thought { content: _ } => {}
+                // reasoning items can only be sent back with their encrypted content
+                Message::Thought {
+                    content,
+                    signature: Some(encrypted_content),
+                    id: Some(id),
+                } if stateless => {
+                    let mut summary = Vec::new();
+                    if !content.is_empty() {
+                        summary.push(OpenAICompletionRequestSummary {
+                            r#type: "summary_text".to_string(),
+                            text: content,
+                        });
+                    }
+                    inputs.push(OpenAICompletionRequestMessage::Reasoning {
+                        r#type: "reasoning".to_string(),
+                        id,
+                        summary,
+                        encrypted_content,
+                    });
+                }
+                Message::Thought { .. } | Message::RedactedThought { .. } => {}
                 Message::User {
                     content,
                     response_id: _,
@@ -62,15 +168,13 @@ impl OpenAICompletionRequest {
                         content,
                     });
                 }
-                Message::Assistant {
-                    content,
-                    response_id,
-                } => {
-                    id = response_id;
-                    inputs.push(OpenAICompletionRequestMessage::Content {
-                        role: "assistant".to_string(),
-                        content,
-                    });
+                Message::Assistant { content, .. } => {
+                    if !content.is_empty() {
+                        inputs.push(OpenAICompletionRequestMessage::Content {
+                            role: "assistant".to_string(),
+                            content,
+                        });
+                    }
                 }
 
                 Message::ToolCall {
@@ -85,10 +189,11 @@ impl OpenAICompletionRequest {
                         name: name,
                     });
                 }
+                // function_call_output has no error flag, the output holds the error
                 Message::ToolOutput {
                     call_id,
                     output,
-                    name: _,
+                    ..
                 } => {
 
                     let arg_string = serde_json::to_string(&output)
@@ -103,18 +208,34 @@ impl OpenAICompletionRequest {
             }
         }
 
+        let mut include = Vec::new();
+        if stateless {
+            include.push("reasoning.encrypted_content".to_string());
+        }
+        if request.sampling.logprobs.is_some() {
+            include.push("message.output_text.logprobs".to_string());
+        }
+
         Ok(Self {
+            temperature: match is_reasoning_model(&request.model) {
+                true => None,
+                false => Some(request.temperature),
+            },
+            top_p: request.sampling.top_p,
+            top_logprobs: request.sampling.logprobs,
             model: request.model,
             instructions: request.system.unwrap_or(String::new()),
             input: inputs,
-            store: true,
+            store: !stateless,
+            include,
             stream: request.stream,
             previous_response_id: id,
             max_output_tokens: request.max_tokens,
-            reasoning: OpenAICompletionRequestReasoning {
-                effort: String::from("low"),
-            },
+            reasoning: request.reasoning.map(OpenAICompletionRequestReasoning::from),
             tools: request.definitions,
+            text: request.response_format.map(|format| OpenAICompletionRequestText {
+                format: format.into(),
+            }),
         })
     }
 }
@@ -133,4 +254,152 @@ impl OpenAIEmbeddingsRequest {
             input: texts.iter().map(|s| s.to_string()).collect()
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::capabilities::completion::request::Sampling;
+
+    fn request(messages: Vec<Message>, conversation_state: ConversationState) -> CompletionRequest {
+        CompletionRequest {
+            model: "gpt-5".to_string(),
+            system: Some("Be brief".to_string()),
+            messages,
+            temperature: 0.5,
+            max_tokens: 4096,
+            stream: false,
+            definitions: Vec::new(),
+            response_format: None,
+            conversation_state,
+            reasoning: None,
+            sampling: Sampling::default(),
+        }
+    }
+
+    fn body(request: CompletionRequest) -> Value {
+        serde_json::to_value(OpenAICompletionRequest::new(request).unwrap()).unwrap()
+    }
+
+    fn user(content: &str) -> Message {
+        Message::User {
+            content: content.to_string(),
+            response_id: None,
+        }
+    }
+
+    fn assistant(content: &str, response_id: &str) -> Message {
+        Message::Assistant {
+            content: content.to_string(),
+            response_id: Some(response_id.to_string()),
+        }
+    }
+
+    // the messages of a tool turn as the tool loop keeps them, then the final answer
+    fn history() -> Vec<Message> {
+        vec![
+            user("Price of AAPL?"),
+            Message::Thought {
+                content: "I need the quote".to_string(),
+                signature: Some("enc-1".to_string()),
+                id: Some("rs_1".to_string()),
+            },
+            assistant("", "resp_1"),
+            Message::ToolCall {
+                arguments: json!({"symbol": "AAPL"}).to_string(),
+                call_id: "call_1".to_string(),
+                name: "get_quote".to_string(),
+            },
+            Message::ToolOutput {
+                call_id: "call_1".to_string(),
+                output: json!({"price": 187.5}),
+                name: "get_quote".to_string(),
+                is_error: false,
+            },
+            assistant("AAPL is at 187.5", "resp_2"),
+            user("And MSFT?"),
+        ]
+    }
+
+    #[test]
+    fn stateless_replays_the_history() {
+        assert_eq!(
+            body(request(history(), ConversationState::Stateless)),
+            json!({
+                "model": "gpt-5",
+                "instructions": "Be brief",
+                "input": [
+                    {"role": "user", "content": "Price of AAPL?"},
+                    {"type": "reasoning", "id": "rs_1", "summary": [
+                        {"type": "summary_text", "text": "I need the quote"}
+                    ], "encrypted_content": "enc-1"},
+                    {"type": "function_call", "arguments": "{\"symbol\":\"AAPL\"}", "call_id": "call_1", "name": "get_quote"},
+                    {"type": "function_call_output", "call_id": "call_1", "output": "{\"price\":187.5}"},
+                    {"role": "assistant", "content": "AAPL is at 187.5"},
+                    {"role": "user", "content": "And MSFT?"}
+                ],
+                "store": false,
+                "include": ["reasoning.encrypted_content"],
+                "stream": false,
+                "max_output_tokens": 4096,
+                "tools": []
+            })
+        );
+    }
+
+    #[test]
+    fn server_managed_sends_the_new_turn() {
+        assert_eq!(
+            body(request(history(), ConversationState::ServerManaged)),
+            json!({
+                "model": "gpt-5",
+                "instructions": "Be brief",
+                "input": [{"role": "user", "content": "And MSFT?"}],
+                "store": true,
+                "stream": false,
+                "previous_response_id": "resp_2",
+                "max_output_tokens": 4096,
+                "tools": []
+            })
+        );
+
+        // within the tool loop the outputs follow the response that asked for the calls,
+        // its reasoning and function calls are stored with it
+        let mut history = history();
+        history.truncate(5);
+        let tool_turn = body(request(history, ConversationState::ServerManaged));
+        assert_eq!(tool_turn["previous_response_id"], "resp_1");
+        assert_eq!(
+            tool_turn["input"],
+            json!([{"type": "function_call_output", "call_id": "call_1", "output": "{\"price\":187.5}"}])
+        );
+
+        // without a response id the full history is sent
+        let messages = vec![user("Hello"), user("Again")];
+        let full = body(request(messages, ConversationState::ServerManaged));
+        assert!(full.get("previous_response_id").is_none());
+        assert_eq!(full["input"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reasoning_without_encrypted_content_dropped() {
+        let messages = vec![
+            user("Hello"),
+            Message::Thought {
+                content: "Greeting".to_string(),
+                signature: None,
+                id: Some("rs_1".to_string()),
+            },
+            user("Again"),
+        ];
+        let body = body(request(messages, ConversationState::Stateless));
+        assert_eq!(
+            body["input"],
+            json!([
+                {"role": "user", "content": "Hello"},
+                {"role": "user", "content": "Again"}
+            ])
+        );
+    }
+}
@@ -1,9 +1,34 @@
 use serde::Deserialize;
 
+use crate::capabilities::completion::response::{FinishReason, TokenLogprob, Usage};
+
 #[derive(Deserialize, Debug)]
 pub struct OpenAICompletionResponse {
     pub id: String,
     pub output: Vec<OpenAICompletionResponseOutput>,
+    pub status: Option<String>,
+    pub incomplete_details: Option<OpenAIIncompleteDetails>,
+    #[serde(default)]
+    pub usage: OpenAIUsage,
+}
+
+// openai has no stop reason, a completed response with function calls stopped for the tools
+pub(super) fn finish_reason(
+    status: Option<&str>,
+    incomplete_reason: Option<&str>,
+    has_tool_calls: bool,
+) -> FinishReason {
+    match status {
+        None | Some("completed") if has_tool_calls => FinishReason::ToolUse,
+        None | Some("completed") => FinishReason::Stop,
+        Some("incomplete") => match incomplete_reason {
+            Some("max_output_tokens") => FinishReason::MaxTokens,
+            Some("content_filter") => FinishReason::ContentFilter,
+            Some(reason) => FinishReason::Other(reason.to_string()),
+            None => FinishReason::Other("incomplete".to_string()),
+        },
+        Some(status) => FinishReason::Other(status.to_string()),
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -24,14 +49,27 @@ pub enum OpenAICompletionResponseOutput {
         name: String,
     },
 
+    // encrypted_content is only returned when it is included in the request
     #[serde(rename = "reasoning")]
-    Reasoning { id: String, summary: Vec<String> },
+    Reasoning {
+        id: String,
+        #[serde(default)]
+        summary: Vec<OpenAICompletionResponseSummary>,
+        encrypted_content: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenAICompletionResponseSummary {
+    pub text: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct OpenAICompletionResponseContent {
     pub r#type: String,
     pub text: String,
+    #[serde(default)]
+    pub logprobs: Vec<TokenLogprob>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,11 +83,77 @@ pub struct OpenAIChunkResponseData {
     pub r#type: String,
     pub response: Option<OpenAIChunkResponseDataResponse>,
     pub delta: Option<String>,
+    #[serde(default)]
+    pub logprobs: Vec<TokenLogprob>,
+    pub output_index: Option<i32>,
+    pub item: Option<OpenAIChunkResponseDataItem>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIChunkResponseDataItem {
+    pub r#type: String,
+    pub id: Option<String>,
+    pub encrypted_content: Option<String>,
+    pub call_id: Option<String>,
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct OpenAIChunkResponseDataResponse {
     pub id: String,
+    pub status: Option<String>,
+    pub usage: Option<OpenAIUsage>,
+    pub incomplete_details: Option<OpenAIIncompleteDetails>,
+    pub error: Option<OpenAIResponseError>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct OpenAIUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub input_tokens_details: OpenAIInputTokensDetails,
+    #[serde(default)]
+    pub output_tokens_details: OpenAIOutputTokensDetails,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct OpenAIInputTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct OpenAIOutputTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+// openai caches prompts automatically, there are no cache creation tokens
+impl From<&OpenAIUsage> for Usage {
+    fn from(usage: &OpenAIUsage) -> Self {
+        Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_input_tokens: usage.input_tokens_details.cached_tokens,
+            cache_creation_tokens: 0,
+            reasoning_tokens: usage.output_tokens_details.reasoning_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIIncompleteDetails {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIResponseError {
+    pub code: Option<String>,
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,198 @@
+use std::sync::{Arc, Mutex};
+
+use agentic_core::capabilities::tools::mcp::{MCPRegistry, MCPServerConfig};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+/*
+    Runs the Streamable HTTP transport against a local stub server.
+    initialize answers with json, the other methods with an event stream.
+*/
+
+const SERVER: &str = "Stub";
+
+#[derive(Clone, Default)]
+struct Stub {
+    // Last-Event-ID headers received on GET
+    resumed_from: Arc<Mutex<Vec<String>>>,
+    // id of the request whose stream dropped, answered on resume
+    dropped_request: Arc<Mutex<Option<Value>>>,
+}
+
+fn event_stream(events: &[(Option<&str>, Value)]) -> Response {
+    let body: String = events
+        .iter()
+        .map(|(id, data)| match id {
+            Some(id) => format!("id: {}\ndata: {}\n\n", id, data),
+            None => format!("data: {}\n\n", data),
+        })
+        .collect();
+    ([("Content-Type", "text/event-stream")], body).into_response()
+}
+
+fn result(id: &Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+async fn handle_post(State(stub): State<Stub>, Json(request): Json<Value>) -> Response {
+    let Some(id) = request.get("id").cloned() else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    match request["method"].as_str().unwrap_or_default() {
+        "initialize" => (
+            [("Mcp-Session-Id", "session-1")],
+            Json(result(
+                &id,
+                json!({
+                    "protocolVersion": request["params"]["protocolVersion"],
+                    "capabilities": {"tools": {}},
+                    "serverInfo": {"name": "stub", "version": "0.1.0"},
+                }),
+            )),
+        )
+            .into_response(),
+        "tools/list" => event_stream(&[(
+            None,
+            result(
+                &id,
+                json!({"tools": [
+                    {"name": "count", "description": "Count to three", "inputSchema": {"type": "object"}},
+                    {"name": "flaky", "description": "Drops the stream", "inputSchema": {"type": "object"}},
+                ]}),
+            ),
+        )]),
+        "tools/call" if request["params"]["name"] == "count" => {
+            let token = request["params"]["_meta"]["progressToken"].clone();
+            let mut events: Vec<(Option<&str>, Value)> = (1..=3)
+                .map(|progress| {
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/progress",
+                        "params": {"progressToken": token, "progress": progress, "total": 3},
+                    });
+                    (None, notification)
+                })
+                .collect();
+            events.push((
+                None,
+                result(&id, json!({"content": [{"type": "text", "text": "3"}]})),
+            ));
+            event_stream(&events)
+        }
+        "tools/call" => {
+            // the stream ends after the first event, the response comes on resume
+            *stub.dropped_request.lock().unwrap() = Some(id);
+            let log = json!({"jsonrpc": "2.0", "method": "notifications/message", "params": {}});
+            event_stream(&[(Some("event-1"), log)])
+        }
+        method => Json(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32601, "message": format!("Method not found: {}", method)}
+        }))
+        .into_response(),
+    }
+}
+
+async fn handle_get(State(stub): State<Stub>, headers: HeaderMap) -> Response {
+    let Some(last_event_id) = headers.get("Last-Event-ID").and_then(|v| v.to_str().ok()) else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    stub.resumed_from
+        .lock()
+        .unwrap()
+        .push(last_event_id.to_string());
+    let Some(id) = stub.dropped_request.lock().unwrap().take() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    event_stream(&[(
+        Some("event-2"),
+        result(
+            &id,
+            json!({"content": [{"type": "text", "text": "resumed"}]}),
+        ),
+    )])
+}
+
+async fn start(stub: Stub) -> String {
+    let router = Router::new()
+        .route("/mcp", post(handle_post).get(handle_get))
+        .with_state(stub);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}/mcp", address)
+}
+
+async fn register(stub: Stub) -> MCPRegistry {
+    let url = start(stub).await;
+    let mut registry = MCPRegistry::new();
+    registry
+        .register_server(MCPServerConfig::http(SERVER, &url, ""))
+        .await
+        .unwrap();
+    registry
+}
+
+#[tokio::test]
+async fn json_and_event_stream_responses() {
+    let url = start(Stub::default()).await;
+    let mut registry = MCPRegistry::new();
+    // initialize is answered with json, tools/list with an event stream
+    let definitions = registry
+        .register_server(MCPServerConfig::http(SERVER, &url, ""))
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(names, ["Stub___count", "Stub___flaky"]);
+    assert!(registry.registry[SERVER].capabilities().tools.is_some());
+}
+
+#[tokio::test]
+async fn progress_notifications() {
+    let registry = register(Stub::default()).await;
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let response = registry
+        .call_tool_with_progress("Stub___count", json!({}), Some(sender))
+        .await
+        .unwrap();
+    assert_eq!(response.text().as_deref(), Some("3"));
+
+    let mut progress = Vec::new();
+    while let Ok(update) = receiver.try_recv() {
+        progress.push((update.progress, update.total));
+    }
+    assert_eq!(
+        progress,
+        [(1.0, Some(3.0)), (2.0, Some(3.0)), (3.0, Some(3.0))]
+    );
+}
+
+#[tokio::test]
+async fn resume_after_stream_drop() {
+    let stub = Stub::default();
+    let registry = register(stub.clone()).await;
+    let response = registry.call_tool("Stub___flaky", json!({})).await.unwrap();
+    assert_eq!(response.text().as_deref(), Some("resumed"));
+    assert_eq!(*stub.resumed_from.lock().unwrap(), ["event-1"]);
+}
+
+#[tokio::test]
+async fn duplicate_server_name_rejected() {
+    let mut registry = register(Stub::default()).await;
+    let url = start(Stub::default()).await;
+    let error = registry
+        .register_server(MCPServerConfig::http(SERVER, &url, ""))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("already registered"), "{}", error);
+    assert_eq!(registry.registry.len(), 1);
+}
@@ -0,0 +1,102 @@
+use agentic_core::capabilities::tools::{
+    mcp::{MCPRegistry, MCPServerConfig},
+    mcp_server::MCPServer,
+    tool::ToolRegistry,
+};
+use axum::{http::StatusCode, response::IntoResponse, routing::post, Json, Router};
+use serde_json::{json, Value};
+
+/*
+    Runs the MCP server router on a local port.
+*/
+
+async fn start(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}/mcp", address)
+}
+
+// upstream server whose tool calls fail with a 500
+async fn failing_upstream(Json(request): Json<Value>) -> axum::response::Response {
+    let Some(id) = request.get("id").cloned() else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "initialize" => json!({
+            "protocolVersion": request["params"]["protocolVersion"],
+            "capabilities": {"tools": {}},
+        }),
+        "tools/list" => json!({"tools": [
+            {"name": "echo", "description": "Echo", "inputSchema": {"type": "object"}}
+        ]}),
+        _ => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    Json(json!({"jsonrpc": "2.0", "id": id, "result": result})).into_response()
+}
+
+#[tokio::test]
+async fn proxied_tool_failure_is_a_tool_error() {
+    let url = start(Router::new().route("/mcp", post(failing_upstream))).await;
+    let mut registry = MCPRegistry::new();
+    registry
+        .register_server(MCPServerConfig::http("Upstream", &url, ""))
+        .await
+        .unwrap();
+    registry.register_tool("Upstream", "echo").await.unwrap();
+
+    let server = MCPServer::new("proxy", ToolRegistry::new()).with_mcp_registry(registry);
+    let response = server
+        .handle_message(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": {"name": "Upstream___echo", "arguments": {}},
+        }))
+        .await
+        .unwrap();
+
+    assert!(response.get("error").is_none());
+    assert_eq!(response["result"]["isError"], json!(true));
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("500"), "{}", text);
+}
+
+#[tokio::test]
+async fn delete_terminates_the_session() {
+    let url = start(MCPServer::new("sessions", ToolRegistry::new()).router()).await;
+    let client = reqwest::Client::new();
+    let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {"protocolVersion": "2025-06-18", "capabilities": {}},
+    });
+    let response = client.post(&url).json(&initialize).send().await.unwrap();
+    let session_id = response.headers()["Mcp-Session-Id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let ping = json!({"jsonrpc": "2.0", "id": 2, "method": "ping"});
+    let status = |response: reqwest::Response| response.status().as_u16();
+    let post = || {
+        client
+            .post(&url)
+            .header("Mcp-Session-Id", &session_id)
+            .json(&ping)
+            .send()
+    };
+    let delete = || {
+        client
+            .delete(&url)
+            .header("Mcp-Session-Id", &session_id)
+            .send()
+    };
+
+    assert_eq!(status(post().await.unwrap()), 200);
+    assert_eq!(status(client.delete(&url).send().await.unwrap()), 400);
+    assert_eq!(status(delete().await.unwrap()), 200);
+    assert_eq!(status(post().await.unwrap()), 404);
+    assert_eq!(status(delete().await.unwrap()), 404);
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use agentic_core::capabilities::tools::mcp::{MCPRegistry, MCPServerConfig};
+use serde_json::json;
+
+/*
+    Runs the stdio transport against examples/scripts/fake_mcp_server.py.
+    Needs python3 on the path: cargo test --test mcp_stdio -- --ignored
+*/
+
+const SERVER: &str = "Fake";
+
+async fn register() -> MCPRegistry {
+    let config = MCPServerConfig::stdio(
+        SERVER,
+        "python3",
+        &["examples/scripts/fake_mcp_server.py", "--test"],
+    );
+    let mut registry = MCPRegistry::new();
+    registry.register_server(config).await.unwrap();
+    registry
+}
+
+async fn server_pid(registry: &MCPRegistry) -> u32 {
+    let response = registry
+        .call_tool("Fake___server_pid", json!({}))
+        .await
+        .unwrap();
+    response.text().unwrap().parse().unwrap()
+}
+
+// a killed child that has not been reaped yet shows up as a zombie
+fn is_running(pid: u32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat.contains(") Z "),
+        Err(_) => false,
+    }
+}
+
+async fn wait_exit(pid: u32) -> bool {
+    for _ in 0..50 {
+        if !is_running(pid) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+#[ignore = "needs python3"]
+async fn handshake_and_paginated_tool_list() {
+    let config = MCPServerConfig::stdio(
+        SERVER,
+        "python3",
+        &["examples/scripts/fake_mcp_server.py", "--test"],
+    );
+    let mut registry = MCPRegistry::new();
+    let definitions = registry.register_server(config).await.unwrap();
+
+    // the server returns one tool per page
+    let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["Fake___get_quote", "Fake___slow_echo", "Fake___server_pid"]
+    );
+
+    let capabilities = registry.registry[SERVER].capabilities();
+    assert!(capabilities.tools.is_some());
+    assert!(capabilities.resources.is_some());
+    registry.close().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs python3"]
+async fn tool_call() {
+    let registry = register().await;
+    let response = registry
+        .call_tool("Fake___get_quote", json!({"symbol": "AAPL"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.into_value(),
+        json!({"symbol": "AAPL", "price": 187.5})
+    );
+    registry.close().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs python3"]
+async fn out_of_order_responses() {
+    let registry = register().await;
+
+    // slow_echo is answered only after the quote, the responses are matched by id
+    let (echo, quote) = tokio::join!(
+        registry.call_tool("Fake___slow_echo", json!({"text": "first"})),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            registry
+                .call_tool("Fake___get_quote", json!({"symbol": "MSFT"}))
+                .await
+        }
+    );
+    assert_eq!(echo.unwrap().text().as_deref(), Some("first"));
+    assert_eq!(
+        quote.unwrap().into_value(),
+        json!({"symbol": "MSFT", "price": 187.5})
+    );
+    registry.close().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs python3"]
+async fn close_stops_the_server() {
+    let registry = register().await;
+    let pid = server_pid(&registry).await;
+    assert!(is_running(pid));
+
+    registry.close().await.unwrap();
+    assert!(wait_exit(pid).await);
+}
+
+#[tokio::test]
+#[ignore = "needs python3"]
+async fn drop_stops_the_server() {
+    let registry = register().await;
+    let pid = server_pid(&registry).await;
+    assert!(is_running(pid));
+
+    drop(registry);
+    assert!(wait_exit(pid).await);
+}
@@ -0,0 +1,369 @@
+use agentic_core::{
+    capabilities::{
+        client::completion::LlmClient,
+        completion::{
+            message::Message,
+            request::{CompletionRequest, ConversationState, ResponseFormat, Sampling},
+            response::{CompletionResponse, CompletionResponseContent, FinishReason, Usage},
+            stream::aggregate,
+        },
+    },
+    http::config::ClientConfig,
+    providers::{
+        anthropic::completion::AnthropicClient, gemini::completion::GeminiClient,
+        openai::completion::OpenAIClient,
+    },
+};
+use axum::{response::IntoResponse, Router};
+use serde_json::{json, Value};
+
+/*
+    Streams recorded provider events from a local stub server and checks the response
+    the aggregator folds them into.
+*/
+
+// Anthropic: thinking with its signature, text, then two tool calls
+const ANTHROPIC_EVENTS: &str = r#"
+event: message_start
+data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"cache_read_input_tokens":4,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Checking the "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"weather"}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig-1"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Let me "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"look."}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: content_block_start
+data: {"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{\"city\":"}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"\"Paris\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":2}
+
+event: content_block_start
+data: {"type":"content_block_start","index":3,"content_block":{"type":"tool_use","id":"toolu_2","name":"time","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":3,"delta":{"type":"input_json_delta","partial_json":"{\"zone\":\"CET\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":3}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":30}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+// Anthropic: a preamble before the response format tool, which is not forced with thinking
+const ANTHROPIC_FORMAT_EVENTS: &str = r#"
+event: message_start
+data: {"type":"message_start","message":{"id":"msg_2","usage":{"input_tokens":12,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Here is the weather:"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\":\"Paris\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":20}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+// OpenAI: a reasoning summary with its encrypted content, text, then two function calls
+// whose argument deltas interleave
+const OPENAI_EVENTS: &str = r#"
+event: response.created
+data: {"type":"response.created","response":{"id":"resp_1","status":"in_progress"}}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":0,"item":{"type":"reasoning","id":"rs_1"}}
+
+event: response.reasoning_summary_text.delta
+data: {"type":"response.reasoning_summary_text.delta","output_index":0,"delta":"Two "}
+
+event: response.reasoning_summary_text.delta
+data: {"type":"response.reasoning_summary_text.delta","output_index":0,"delta":"lookups"}
+
+event: response.output_item.done
+data: {"type":"response.output_item.done","output_index":0,"item":{"type":"reasoning","id":"rs_1","encrypted_content":"enc-1"}}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":1,"item":{"type":"message","id":"msg_1"}}
+
+event: response.output_text.delta
+data: {"type":"response.output_text.delta","output_index":1,"delta":"Checking "}
+
+event: response.output_text.delta
+data: {"type":"response.output_text.delta","output_index":1,"delta":"both."}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":2,"item":{"type":"function_call","call_id":"call_a","name":"weather"}}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":3,"item":{"type":"function_call","call_id":"call_b","name":"time"}}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","output_index":2,"delta":"{\"city\":"}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","output_index":3,"delta":"{\"zone\":"}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","output_index":2,"delta":"\"Paris\"}"}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","output_index":3,"delta":"\"CET\"}"}
+
+event: response.output_item.done
+data: {"type":"response.output_item.done","output_index":2,"item":{"type":"function_call","call_id":"call_a","name":"weather"}}
+
+event: response.output_item.done
+data: {"type":"response.output_item.done","output_index":3,"item":{"type":"function_call","call_id":"call_b","name":"time"}}
+
+event: response.completed
+data: {"type":"response.completed","response":{"id":"resp_1","status":"completed","usage":{"input_tokens":20,"output_tokens":15,"input_tokens_details":{"cached_tokens":8},"output_tokens_details":{"reasoning_tokens":5}}}}
+
+"#;
+
+// Gemini: a thought summary with its signature, text, then two function calls, every
+// function call delta carries the complete arguments
+const GEMINI_EVENTS: &str = r#"
+data: {"event_type":"interaction.start","interaction":{"id":"int_1","status":"in_progress"}}
+
+data: {"event_type":"content.start","index":0,"content":{"type":"thought"}}
+
+data: {"event_type":"content.delta","index":0,"delta":{"type":"thought_summary","content":{"type":"text","text":"Planning"}}}
+
+data: {"event_type":"content.delta","index":0,"delta":{"type":"thought_signature","signature":"gsig-1"}}
+
+data: {"event_type":"content.stop","index":0}
+
+data: {"event_type":"content.start","index":1,"content":{"type":"text"}}
+
+data: {"event_type":"content.delta","index":1,"delta":{"type":"text","text":"On "}}
+
+data: {"event_type":"content.delta","index":1,"delta":{"type":"text","text":"it."}}
+
+data: {"event_type":"content.stop","index":1}
+
+data: {"event_type":"content.delta","index":2,"delta":{"type":"function_call","id":"fc_1","name":"weather","arguments":{"city":"Paris"}}}
+
+data: {"event_type":"content.delta","index":3,"delta":{"type":"function_call","id":"fc_2","name":"time","arguments":{"zone":"CET"}}}
+
+data: {"event_type":"content.delta","index":2,"delta":{"type":"function_call","id":"fc_1","name":"weather","arguments":{"city":"Paris"}}}
+
+data: {"event_type":"content.stop","index":2}
+
+data: {"event_type":"content.stop","index":3}
+
+data: {"event_type":"interaction.complete","interaction":{"id":"int_1","status":"requires_action","usage":{"total_input_tokens":30,"total_output_tokens":10,"total_cached_tokens":6,"total_thought_tokens":4}}}
+
+"#;
+
+// serves the recorded events on every path
+async fn start(events: &'static str) -> String {
+    let router = Router::new().fallback(move || async move {
+        ([("Content-Type", "text/event-stream")], events).into_response()
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}", address)
+}
+
+fn request(model: &str) -> CompletionRequest {
+    CompletionRequest {
+        model: model.to_string(),
+        system: None,
+        messages: vec![Message::User {
+            content: "Weather and time in Paris?".to_string(),
+            response_id: None,
+        }],
+        temperature: 1.0,
+        max_tokens: 1024,
+        stream: true,
+        definitions: Vec::new(),
+        response_format: None,
+        conversation_state: ConversationState::default(),
+        reasoning: None,
+        sampling: Sampling::default(),
+    }
+}
+
+async fn stream(client: impl LlmClient, model: &str) -> CompletionResponse {
+    let stream = client.complete_with_stream(request(model)).await.unwrap();
+    aggregate(stream).await.unwrap()
+}
+
+// contents as comparable values, in the order of the response
+fn contents(response: &CompletionResponse) -> Vec<Value> {
+    response
+        .contents
+        .iter()
+        .map(|content| match content {
+            CompletionResponseContent::Text(text) => json!({"text": text}),
+            CompletionResponseContent::Thought {
+                text,
+                signature,
+                id,
+            } => json!({"thought": text, "signature": signature, "id": id}),
+            CompletionResponseContent::RedactedThought { data } => json!({"redacted": data}),
+            CompletionResponseContent::ToolCall(call) => {
+                json!({"call": call.id, "name": call.name, "arguments": call.arguments})
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn anthropic_events() {
+    let config = ClientConfig::default().with_base_url(&start(ANTHROPIC_EVENTS).await);
+    let client = AnthropicClient::with_config("key".to_string(), &config).unwrap();
+    let response = stream(client, "claude-sonnet-4-5").await;
+
+    assert_eq!(
+        contents(&response),
+        [
+            json!({"thought": "Checking the weather", "signature": "sig-1", "id": null}),
+            json!({"text": "Let me look."}),
+            json!({"call": "toolu_1", "name": "weather", "arguments": {"city": "Paris"}}),
+            json!({"call": "toolu_2", "name": "time", "arguments": {"zone": "CET"}}),
+        ]
+    );
+    assert_eq!(response.response_id, "msg_1");
+    assert_eq!(response.finish_reason, FinishReason::ToolUse);
+    assert_eq!(
+        response.usage,
+        Usage {
+            input_tokens: 16,
+            output_tokens: 30,
+            cached_input_tokens: 4,
+            cache_creation_tokens: 0,
+            reasoning_tokens: 0,
+        }
+    );
+}
+
+#[tokio::test]
+async fn anthropic_format_tool_events() {
+    let config = ClientConfig::default().with_base_url(&start(ANTHROPIC_FORMAT_EVENTS).await);
+    let client = AnthropicClient::with_config("key".to_string(), &config).unwrap();
+    let mut request = request("claude-sonnet-4-5");
+    request.response_format = Some(ResponseFormat::json_schema(
+        "weather",
+        json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+    ));
+    let stream = client.complete_with_stream(request).await.unwrap();
+    let response = aggregate(stream).await.unwrap();
+
+    // the preamble is not joined with the json of the format tool
+    assert_eq!(
+        contents(&response),
+        [json!({"text": r#"{"city":"Paris"}"#})]
+    );
+    assert_eq!(response.finish_reason, FinishReason::Stop);
+}
+
+#[tokio::test]
+async fn openai_events() {
+    let config = ClientConfig::default().with_base_url(&start(OPENAI_EVENTS).await);
+    let client = OpenAIClient::with_config("key".to_string(), &config).unwrap();
+    let response = stream(client, "gpt-5").await;
+
+    assert_eq!(
+        contents(&response),
+        [
+            json!({"thought": "Two lookups", "signature": "enc-1", "id": "rs_1"}),
+            json!({"text": "Checking both."}),
+            json!({"call": "call_a", "name": "weather", "arguments": {"city": "Paris"}}),
+            json!({"call": "call_b", "name": "time", "arguments": {"zone": "CET"}}),
+        ]
+    );
+    assert_eq!(response.response_id, "resp_1");
+    assert_eq!(response.finish_reason, FinishReason::ToolUse);
+    assert_eq!(
+        response.usage,
+        Usage {
+            input_tokens: 20,
+            output_tokens: 15,
+            cached_input_tokens: 8,
+            cache_creation_tokens: 0,
+            reasoning_tokens: 5,
+        }
+    );
+}
+
+#[tokio::test]
+async fn gemini_events() {
+    let config = ClientConfig::default().with_base_url(&start(GEMINI_EVENTS).await);
+    let client = GeminiClient::with_config("key".to_string(), &config).unwrap();
+    let response = stream(client, "gemini-3-pro-preview").await;
+
+    // the repeated arguments of fc_1 replace the first ones
+    assert_eq!(
+        contents(&response),
+        [
+            json!({"thought": "Planning", "signature": "gsig-1", "id": null}),
+            json!({"text": "On it."}),
+            json!({"call": "fc_1", "name": "weather", "arguments": {"city": "Paris"}}),
+            json!({"call": "fc_2", "name": "time", "arguments": {"zone": "CET"}}),
+        ]
+    );
+    assert_eq!(response.response_id, "int_1");
+    assert_eq!(response.finish_reason, FinishReason::ToolUse);
+    assert_eq!(
+        response.usage,
+        Usage {
+            input_tokens: 30,
+            output_tokens: 14,
+            cached_input_tokens: 6,
+            cache_creation_tokens: 0,
+            reasoning_tokens: 4,
+        }
+    );
+}